@@ -0,0 +1,85 @@
+struct Light {
+    position: vec2<f32>,
+    radius: f32,
+    intensity: f32,
+    color: vec4<f32>,
+    direction: vec2<f32>,
+    cone_cos: f32,
+};
+
+struct LightingSettings {
+    ambient: vec4<f32>,
+    level_origin: vec2<f32>,
+    level_cells: vec2<f32>,
+    grid_size: f32,
+    light_count: u32,
+    lights: array<Light, 32>,
+};
+
+@group(1) @binding(0)
+var<uniform> settings: LightingSettings;
+
+@group(1) @binding(1)
+var occluders: texture_2d<f32>;
+
+fn cell_at(position: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(floor((position - settings.level_origin) / settings.grid_size));
+}
+
+fn is_wall(cell: vec2<i32>) -> bool {
+    let cells = vec2<i32>(settings.level_cells);
+    if cell.x < 0 || cell.y < 0 || cell.x >= cells.x || cell.y >= cells.y {
+        return false;
+    }
+    return textureLoad(occluders, cell, 0).r > 0.5;
+}
+
+// Must match `LightMap::blocked`
+fn blocked(start: vec2<f32>, end: vec2<f32>) -> bool {
+    if settings.grid_size <= 0.0 {
+        return false;
+    }
+    let end_cell = cell_at(end);
+    let steps = i32(min(ceil(length(end - start) / (settings.grid_size / 4.0)), 256.0));
+    for (var step = 0; step < steps; step++) {
+        let cell = cell_at(mix(start, end, f32(step) / f32(steps)));
+        if all(cell == end_cell) {
+            return false;
+        }
+        if is_wall(cell) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Must match `LightSource::contribution`
+fn contribution(light: Light, pixel: vec2<f32>) -> f32 {
+    let offset = pixel - light.position;
+    let distance = length(offset);
+    if distance >= light.radius {
+        return 0.0;
+    }
+    let falloff = 1.0 - distance / light.radius;
+    var amount = falloff * falloff * light.intensity;
+    if light.cone_cos > -1.0 && distance > 0.0 {
+        amount *= smoothstep(light.cone_cos, light.cone_cos + 0.05, dot(offset, light.direction) / distance);
+    }
+    return amount;
+}
+
+@fragment
+fn fragment(
+    #import bevy_sprite::mesh2d_vertex_output
+) -> @location(0) vec4<f32> {
+    let pixel = world_position.xy;
+    var light = settings.ambient.rgb;
+    for (var i = 0u; i < settings.light_count; i++) {
+        let source = settings.lights[i];
+        let amount = contribution(source, pixel);
+        if amount > 0.0 && !blocked(source.position, pixel) {
+            light += source.color.rgb * amount;
+        }
+    }
+    return vec4<f32>(min(light, vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,136 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::utils
+
+struct PostProcessingSettings {
+    passes: array<vec4<u32>, 2>,
+    pass_count: u32,
+    chromatic_aberration: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_softness: f32,
+    vignette_color: vec4<f32>,
+    scanline_intensity: f32,
+    scanline_count: f32,
+    grade_strength: f32,
+    flash_intensity: f32,
+    flash_color: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> settings: PostProcessingSettings;
+
+@group(1) @binding(1)
+var texture: texture_2d<f32>;
+
+@group(1) @binding(2)
+var our_sampler: sampler;
+
+@group(1) @binding(3)
+var lut: texture_2d<f32>;
+
+@group(1) @binding(4)
+var lut_sampler: sampler;
+
+// Pass ids must match `PostEffect` on the rust side:
+// 0 chromatic aberration, 1 vignette, 2 scanlines, 3 colour grade, 4 flash
+let CHROMATIC_ABERRATION: u32 = 0u;
+
+// The lut is 16 slices of 16x16 laid out horizontally
+let LUT_SIZE: f32 = 16.0;
+
+fn pass_at(index: u32) -> u32 {
+    return settings.passes[index / 4u][index % 4u];
+}
+
+fn vignette(uv: vec2<f32>, color: vec3<f32>) -> vec3<f32> {
+    let dist = distance(uv, vec2<f32>(0.5, 0.5));
+    let amount = smoothstep(settings.vignette_radius - settings.vignette_softness, settings.vignette_radius, dist);
+    return mix(color, settings.vignette_color.rgb, amount * settings.vignette_intensity);
+}
+
+fn scanlines(uv: vec2<f32>, color: vec3<f32>) -> vec3<f32> {
+    let band = 0.5 - 0.5 * cos(uv.y * settings.scanline_count * 6.2831853);
+    return color * (1.0 - settings.scanline_intensity * band);
+}
+
+fn color_grade(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    let slice = c.b * (LUT_SIZE - 1.0);
+    let slice_low = floor(slice);
+    let slice_high = min(slice_low + 1.0, LUT_SIZE - 1.0);
+    let x = (c.r * (LUT_SIZE - 1.0) + 0.5) / (LUT_SIZE * LUT_SIZE);
+    let y = (c.g * (LUT_SIZE - 1.0) + 0.5) / LUT_SIZE;
+    let low = textureSampleLevel(lut, lut_sampler, vec2<f32>(x + slice_low / LUT_SIZE, y), 0.0).rgb;
+    let high = textureSampleLevel(lut, lut_sampler, vec2<f32>(x + slice_high / LUT_SIZE, y), 0.0).rgb;
+    let graded = mix(low, high, slice - slice_low);
+    return mix(color, graded, settings.grade_strength);
+}
+
+fn flash(color: vec3<f32>) -> vec3<f32> {
+    return mix(color, settings.flash_color.rgb, settings.flash_intensity * settings.flash_color.a);
+}
+
+fn apply_pass(pass_id: u32, uv: vec2<f32>, color: vec3<f32>) -> vec3<f32> {
+    var result = color;
+    switch pass_id {
+        case 1u: {
+            result = vignette(uv, color);
+        }
+        case 2u: {
+            result = scanlines(uv, color);
+        }
+        case 3u: {
+            result = color_grade(color);
+        }
+        case 4u: {
+            result = flash(color);
+        }
+        default: {}
+    }
+    return result;
+}
+
+// Runs the passes in [start, end) over the source image at uv, skipping chromatic aberration
+fn run_passes(uv: vec2<f32>, start: u32, end: u32) -> vec3<f32> {
+    var color = textureSampleLevel(texture, our_sampler, uv, 0.0).rgb;
+    for (var i = start; i < end; i++) {
+        color = apply_pass(pass_at(i), uv, color);
+    }
+    return color;
+}
+
+@fragment
+fn fragment(
+    @builtin(position) position: vec4<f32>,
+    #import bevy_sprite::mesh2d_vertex_output
+) -> @location(0) vec4<f32> {
+    // Get screen position with coordinates from 0 to 1
+    let uv = coords_to_viewport_uv(position.xy, view.viewport);
+
+    // Chromatic aberration is the only pass that moves samples around, so everything
+    // before it gets evaluated once per shifted colour channel
+    var aberration_index = settings.pass_count;
+    for (var i = 0u; i < settings.pass_count; i++) {
+        if pass_at(i) == CHROMATIC_ABERRATION {
+            aberration_index = i;
+            break;
+        }
+    }
+
+    var color: vec3<f32>;
+    if aberration_index < settings.pass_count {
+        let offset_strength = settings.chromatic_aberration;
+        color = vec3<f32>(
+            run_passes(uv + vec2<f32>(offset_strength, -offset_strength), 0u, aberration_index).r,
+            run_passes(uv + vec2<f32>(-offset_strength, 0.0), 0u, aberration_index).g,
+            run_passes(uv + vec2<f32>(0.0, offset_strength), 0u, aberration_index).b
+        );
+        for (var i = aberration_index + 1u; i < settings.pass_count; i++) {
+            color = apply_pass(pass_at(i), uv, color);
+        }
+    } else {
+        color = run_passes(uv, 0u, settings.pass_count);
+    }
+
+    return vec4<f32>(color, 1.0);
+}
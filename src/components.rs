@@ -1,18 +1,24 @@
 use bevy::{
     prelude::*,
     reflect::TypeUuid,
-    render::render_resource::{AsBindGroup, ShaderRef},
+    render::render_resource::{AsBindGroup, ShaderRef, ShaderType},
     sprite::Material2d,
 };
-use bevy_ecs_ldtk::{EntityInstance, LdtkEntity, LdtkIntCell, Worldly};
+use bevy_ecs_ldtk::{EntityInstance, LdtkEntity, LdtkIntCell};
 use bevy_rapier2d::prelude::*;
 
-use crate::states::game::components::{Items, NoiseValue};
+use crate::states::game::{
+    colliders::ColliderSpec,
+    components::{Items, NoiseValue},
+    doors::KeyRing,
+    lighting::Illumination,
+};
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub enum GameState {
     Splashscreen,
     Menu,
+    LevelSelect,
     Game,
 }
 #[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
@@ -31,49 +37,102 @@ pub struct ColliderBundle {
 pub struct Splashscreen;
 #[derive(Component)]
 pub struct Menu;
+#[derive(Component)]
+pub struct LevelSelect;
 #[derive(Default, Component, Clone)]
 pub struct Game;
 
 #[derive(Resource)]
 pub struct InGame(pub bool);
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Player;
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Wall;
+/// The LDtk project the game is played in, relative to the assets folder
+#[derive(Resource, Clone)]
+pub struct MapPath(pub String);
 
-#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
-pub struct WallBundle {
-    wall: Wall,
+impl Default for MapPath {
+    fn default() -> Self {
+        MapPath("Map.ldtk".to_string())
+    }
 }
 
-#[derive(Default, Bundle, LdtkEntity)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Player;
+
+/// Spawned at the PlayerStart of the selected level, see `spawn_player`
+#[derive(Default, Bundle)]
 pub struct PlayerBundle {
     pub player_tag: Player,
-    #[sprite_bundle("player.png")]
     #[bundle]
     pub sprite_bundle: SpriteBundle,
     game: Game,
-    #[worldly]
-    pub worldly: Worldly,
-    #[bundle]
     pub held_item: Items,
     pub colliding_entities: CollidingEntities,
     pub noise: NoiseValue,
+    pub illumination: Illumination,
+    pub keys: KeyRing,
+    pub collider: ColliderSpec,
+}
+
+impl PlayerBundle {
+    pub fn new(translation: Vec3, collider: ColliderSpec, asset_server: &AssetServer) -> Self {
+        PlayerBundle {
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load("player.png"),
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            collider,
+            ..default()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct PlayerStart;
+
+#[derive(Default, Bundle, LdtkEntity)]
+pub struct PlayerStartBundle {
+    pub start: PlayerStart,
+    /// The player's collider is set up on the PlayerStart, see `ColliderSpec`
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
 }
 
 #[derive(AsBindGroup, TypeUuid, Clone)]
 #[uuid = "bc2f08eb-a0fb-43f1-a908-54871ea597d5"]
 pub struct PostProcessingMaterial {
+    #[uniform(0)]
+    pub settings: PostProcessingSettings,
     /// In this example, this image will be the result of the main camera.
-    #[texture(0)]
-    #[sampler(1)]
+    #[texture(1)]
+    #[sampler(2)]
     pub source_image: Handle<Image>,
+    /// Colour grading lookup table, see `post_processing::identity_lut` for the layout
+    #[texture(3)]
+    #[sampler(4)]
+    pub lut: Handle<Image>,
+}
+
+/// Uniform mirror of `PostProcessingStack`, laid out to match `post_processing.wgsl`
+#[derive(ShaderType, Clone, Default)]
+pub struct PostProcessingSettings {
+    /// Effect ids in the order they are applied, four per vector
+    pub passes: [UVec4; 2],
+    pub pass_count: u32,
+    pub chromatic_aberration: f32,
+    pub vignette_intensity: f32,
+    pub vignette_radius: f32,
+    pub vignette_softness: f32,
+    pub vignette_color: Color,
+    pub scanline_intensity: f32,
+    pub scanline_count: f32,
+    pub grade_strength: f32,
+    pub flash_intensity: f32,
+    pub flash_color: Color,
 }
 
 impl Material2d for PostProcessingMaterial {
     fn fragment_shader() -> ShaderRef {
-        "shaders/custom_material_chromatic_aberration.wgsl".into()
+        "shaders/post_processing.wgsl".into()
     }
 }
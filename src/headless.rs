@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use bevy::{app::ScheduleRunnerSettings, input::InputPlugin, prelude::*};
+use bevy_ecs_ldtk::LdtkPlugin;
+
+use crate::{add_gameplay, components::GameState};
+
+/// Runs the game logic at a fixed 60 updates a second, without a window or a gpu.
+/// Starts straight in the Game state, since the menus need a window to be of any use.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )));
+    add_headless_plugins(&mut app);
+    add_gameplay(&mut app, GameState::Game);
+    app
+}
+
+/// The engine plugins the gameplay needs, in place of `DefaultPlugins`.
+/// The asset types normally registered by the render plugins are added by hand,
+/// since Map.ldtk and the LDtk tilemaps still create images, atlases and shaders.
+pub fn add_headless_plugins(app: &mut App) -> &mut App {
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(ImagePlugin::default_nearest())
+        .add_asset::<Shader>()
+        .add_asset::<Mesh>()
+        .add_asset::<TextureAtlas>()
+        .add_plugin(LdtkPlugin)
+}
@@ -0,0 +1,333 @@
+use bevy::{
+    app::AppExit,
+    diagnostic::{EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    input::InputSystem,
+    prelude::*,
+    sprite::Material2dPlugin,
+};
+use bevy_ecs_ldtk::{
+    prelude::RegisterLdtkObjects, LdtkSettings, LevelBackground, LevelSelection,
+    LevelSpawnBehavior, SetClearColor,
+};
+use bevy_rapier2d::{
+    prelude::{NoUserData, RapierConfiguration, RapierPhysicsPlugin},
+    render::RapierDebugRenderPlugin,
+};
+use bevy_tweening::TweeningPlugin;
+use iyes_loopless::prelude::*;
+pub mod components;
+use components::*;
+pub mod headless;
+pub mod logging;
+pub mod save;
+pub mod systems;
+pub mod tuning;
+use sark_pathfinding::PathMap2d;
+use save::{SaveData, SaveFile};
+use systems::*;
+use tuning::{Tuning, TuningLoader};
+pub mod states;
+use states::{
+    game::{
+        animation::{AnimationFinished, SpriteAnimations},
+        aseprite::AsepriteLoader,
+        camera::ScreenShake,
+        components::{
+            GlassBottle, Items, MainEnemyBundle, NoiseEvent, PathInit, PathfindingMap,
+            WorldMouseCoords,
+        },
+        console::{Cheats, Console, RegisterConsoleCommand},
+        debug::DebugOverlay,
+        doors::{DoorBundle, KeyBundle},
+        interaction::{InteractEvent, InteractionTarget},
+        lighting::{LightBundle, LightMap, LightingMaterial},
+        map_screen::MapScreen,
+        objectives::{AvoidDetectionBundle, CollectibleBundle, ExitBundle, LevelProgress},
+        post_processing::PostProcessingStack,
+        replay::{GameRng, ReplayPlayback, ReplayRecorder, ReplaySystem},
+        systems::{hide_cursor, unhide_cursor},
+        terrain::{RegisterTerrain, TerrainMap},
+        transitions::RoomTransition,
+        wall_chunks::TerrainCells,
+    },
+    *,
+};
+
+/// Game logic: LDtk entities, physics, AI and everything that happens in the Game state.
+/// Needs assets, input, time and the LDtk plugin, but no window or gpu,
+/// so it is shared by the game and `headless::headless_app`.
+pub fn add_gameplay(app: &mut App, initial_state: GameState) -> &mut App {
+    let tuning = Tuning::read_at_startup();
+    let physics = RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(tuning.pixels_per_meter);
+    app.add_plugin(physics)
+        .insert_resource(RapierConfiguration {
+            gravity: Vec2::new(0.0, 0.0),
+            ..Default::default()
+        })
+        .insert_resource(LevelSelection::Index(0))
+        .insert_resource(LdtkSettings {
+            level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                load_level_neighbors: true,
+            },
+            set_clear_color: SetClearColor::FromLevelBackground,
+            level_background: LevelBackground::Nonexistent,
+            ..Default::default()
+        })
+        .add_loopless_state(initial_state)
+        .add_enter_system(GameState::Game, game::systems::setup)
+        .add_enter_system(GameState::Game, game::objectives::start_level)
+        // Before setup, which spawns the map `start_replay` may put back
+        .add_enter_system(
+            GameState::Game,
+            game::replay::start_replay.before(game::systems::setup),
+        )
+        .add_exit_system(GameState::Game, despawn_with::<Game>)
+        .add_exit_system(GameState::Game, game::replay::save_recording)
+        .add_exit_system(GameState::Game, game::transitions::end_room_transition)
+        .add_exit_system(GameState::Game, game::wall_chunks::clear_terrain_cells)
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            game::replay::record_input
+                .run_in_state(GameState::Game)
+                .run_if_resource_exists::<ReplayRecorder>()
+                .run_unless_resource_exists::<ReplayPlayback>()
+                .label(ReplaySystem)
+                .after(InputSystem),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            game::replay::play_input
+                .run_in_state(GameState::Game)
+                .run_if_resource_exists::<ReplayPlayback>()
+                .label(ReplaySystem)
+                .after(InputSystem),
+        )
+        .add_system_to_stage(
+            CoreStage::Last,
+            game::replay::save_recording.run_on_event::<AppExit>(),
+        )
+        .add_system(game::systems::input.run_in_state(GameState::Game))
+        .add_system(game::colliders::attach_colliders.run_in_state(GameState::Game))
+        .add_system(
+            game::systems::move_enemies
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(
+            game::crowd::avoid_crowding
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>()
+                .after(game::systems::move_enemies),
+        )
+        // After the commands of `Update`, so it sees the terrain tiles they despawned
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            game::wall_chunks::rebuild_wall_chunks.run_in_state(GameState::Game),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            game::lighting::update_light_map
+                .run_in_state(GameState::Game)
+                .after(game::wall_chunks::rebuild_wall_chunks),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            game::terrain::update_terrain_map
+                .run_in_state(GameState::Game)
+                .after(game::wall_chunks::rebuild_wall_chunks),
+        )
+        .add_system(game::systems::spawn_player.run_in_state(GameState::Game))
+        .add_system(
+            game::systems::move_player
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::interaction::find_interaction_target.run_in_state(GameState::Game))
+        .add_system(
+            game::interaction::interact
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::systems::pick_up_items.run_in_state(GameState::Game))
+        .add_system(game::systems::hear_noise.run_in_state(GameState::Game))
+        .add_system(game::doors::use_doors.run_in_state(GameState::Game))
+        .add_system(game::doors::collect_keys.run_in_state(GameState::Game))
+        .add_system(game::doors::update_doors.run_in_state(GameState::Game))
+        .add_system(
+            game::doors::update_door_nav
+                .run_in_state(GameState::Game)
+                .after(game::systems::create_collision_map),
+        )
+        .add_system(
+            game::doors::enemies_open_doors
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::objectives::collect_objectives.run_in_state(GameState::Game))
+        .add_system(
+            game::objectives::detect_player
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::objectives::reach_exit.run_in_state(GameState::Game))
+        .add_system(game::objectives::continue_from_results.run_in_state(GameState::Game))
+        .add_system(
+            game::systems::reload_map
+                .run_in_state(GameState::Game)
+                .before(game::systems::create_collision_map),
+        )
+        .add_system(game::systems::create_collision_map.run_in_state(GameState::Game))
+        .add_system(game::systems::face_towards_cursor.run_in_state(GameState::Game))
+        .add_system(game::systems::show_held_item.run_in_state(GameState::Game))
+        .add_system(
+            game::systems::update_level_selection
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::post_processing::fade_flash.run_in_state(GameState::Game))
+        .add_system(game::terrain::footsteps.run_in_state(GameState::Game))
+        .add_system(game::lighting::update_illumination.run_in_state(GameState::Game))
+        .add_system(game::console::run_console_commands)
+        .add_system(
+            game::transitions::advance_room_transition
+                .run_in_state(GameState::Game)
+                .run_if_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::map_screen::explore_rooms.run_in_state(GameState::Game))
+        .add_system(save::write_save)
+        .add_system(tuning::apply_tuning)
+        .add_system(print_current_state)
+        .register_ldtk_entity::<PlayerStartBundle>("PlayerStart")
+        .register_ldtk_entity::<MainEnemyBundle>("MainEnemy")
+        .register_ldtk_entity::<GlassBottle>("GlassBottle")
+        .register_ldtk_entity::<LightBundle>("PointLight")
+        .register_ldtk_entity::<LightBundle>("ConeLight")
+        .register_ldtk_entity::<DoorBundle>("Door")
+        .register_ldtk_entity::<KeyBundle>("Key")
+        .register_ldtk_entity::<ExitBundle>("Exit")
+        .register_ldtk_entity::<CollectibleBundle>("Collectible")
+        .register_ldtk_entity::<AvoidDetectionBundle>("AvoidDetection")
+        .register_terrain()
+        .insert_resource(InGame(false))
+        .init_resource::<MapPath>()
+        .insert_resource(Items::GlassBottle)
+        .insert_resource(WorldMouseCoords(Vec2 { x: 0.0, y: 0.0 }))
+        .insert_resource(PathfindingMap {
+            path_map: PathMap2d::new([100, 100]),
+        })
+        .insert_resource(PathInit(false))
+        .insert_resource(PostProcessingStack::default())
+        .insert_resource(LightMap::default())
+        .init_resource::<TerrainMap>()
+        .init_resource::<TerrainCells>()
+        .insert_resource(InteractionTarget::default())
+        .insert_resource(LevelProgress::default())
+        .insert_resource(GameRng::default())
+        .insert_resource(tuning)
+        .add_asset::<Tuning>()
+        .add_asset_loader(TuningLoader)
+        .add_startup_system(tuning::load_tuning)
+        .init_resource::<Console>()
+        .init_resource::<Cheats>()
+        .init_resource::<ScreenShake>()
+        .init_resource::<SaveData>()
+        .init_resource::<SaveFile>()
+        .register_console_command(game::console::HELP)
+        .register_console_command(game::console::TP)
+        .register_console_command(game::console::LEVEL)
+        .register_console_command(game::console::GIVE)
+        .register_console_command(game::console::SPAWN)
+        .register_console_command(game::console::GOD)
+        .register_console_command(game::console::AI)
+        .register_console_command(game::console::TIMESCALE)
+        .register_console_command(game::console::STATE)
+        .add_event::<InteractEvent>()
+        .add_event::<NoiseEvent>()
+}
+
+/// Everything that needs a window or a gpu: menus, cameras, mouse aiming and the render passes
+pub fn add_presentation(app: &mut App) -> &mut App {
+    app.add_plugin(TweeningPlugin)
+        // Off until the debug overlay is shown, see `debug::toggle_collider_debug`
+        .add_plugin(RapierDebugRenderPlugin {
+            enabled: false,
+            ..default()
+        })
+        .add_plugin(FrameTimeDiagnosticsPlugin)
+        .add_plugin(EntityCountDiagnosticsPlugin)
+        .add_plugin(Material2dPlugin::<PostProcessingMaterial>::default())
+        .add_plugin(Material2dPlugin::<LightingMaterial>::default())
+        .add_enter_system(GameState::Splashscreen, splashscreen::setup)
+        .add_enter_system(GameState::Menu, menu::setup)
+        .add_enter_system(GameState::LevelSelect, level_select::setup)
+        .add_enter_system(GameState::Game, game::systems::setup_view)
+        .add_enter_system(GameState::Game, hide_cursor)
+        .add_enter_system(GameState::Game, game::debug::spawn_debug_stats)
+        .add_enter_system(GameState::Game, game::console::spawn_console)
+        .add_exit_system(GameState::Splashscreen, despawn_with::<Splashscreen>)
+        .add_exit_system(GameState::Menu, despawn_with::<Menu>)
+        .add_exit_system(GameState::LevelSelect, despawn_with::<LevelSelect>)
+        .add_exit_system(GameState::Game, unhide_cursor)
+        .add_system(splashscreen::update.run_in_state(GameState::Splashscreen))
+        .add_system(splashscreen::input.run_in_state(GameState::Splashscreen))
+        .add_system(menu::input.run_in_state(GameState::Menu))
+        .add_system(level_select::populate_level_list.run_in_state(GameState::LevelSelect))
+        .add_system(level_select::input.run_in_state(GameState::LevelSelect))
+        // Before the replay systems, so recordings see this frame's aim
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            game::systems::aiming
+                .run_in_state(GameState::Game)
+                .run_unless_resource_exists::<ReplayPlayback>()
+                .before(ReplaySystem)
+                .after(InputSystem),
+        )
+        // Takes the keyboard from everything else while the console is open
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            game::console::console_input
+                .run_in_state(GameState::Game)
+                .before(ReplaySystem)
+                .after(InputSystem),
+        )
+        .add_system(game::console::update_console.run_in_state(GameState::Game))
+        .add_system(game::animation::attach_animations.run_in_state(GameState::Game))
+        .add_system(game::animation::choose_player_clip.run_in_state(GameState::Game))
+        .add_system(game::animation::choose_enemy_clip.run_in_state(GameState::Game))
+        .add_system(
+            game::animation::animate_sprites
+                .run_in_state(GameState::Game)
+                .after(game::animation::choose_player_clip)
+                .after(game::animation::choose_enemy_clip),
+        )
+        .add_system(game::systems::cursor.run_in_state(GameState::Game))
+        .add_system(game::systems::scale_render_image.run_in_state(GameState::Game))
+        .add_system(game::interaction::update_interaction_prompt.run_in_state(GameState::Game))
+        .add_system(game::camera::follow_player.run_in_state(GameState::Game))
+        .add_system(game::camera::shake_on_noise.run_in_state(GameState::Game))
+        .add_system(
+            game::transitions::fade_between_rooms
+                .run_in_state(GameState::Game)
+                .run_if_resource_exists::<RoomTransition>(),
+        )
+        .add_system(game::post_processing::update_post_processing.run_in_state(GameState::Game))
+        .add_system(game::lighting::update_lighting.run_in_state(GameState::Game))
+        .add_system(game::map_screen::toggle_map_screen.run_in_state(GameState::Game))
+        .add_system(game::map_screen::update_map_screen.run_in_state(GameState::Game))
+        .add_system(game::debug::toggle_debug_overlay.run_in_state(GameState::Game))
+        .add_system(game::debug::toggle_collider_debug)
+        .add_system(game::debug::draw_nav_grid.run_in_state(GameState::Game))
+        .add_system(game::debug::draw_enemy_paths.run_in_state(GameState::Game))
+        .add_system(game::debug::draw_noise.run_in_state(GameState::Game))
+        .add_system(game::debug::draw_vision.run_in_state(GameState::Game))
+        .add_system(game::debug::update_debug_stats.run_in_state(GameState::Game))
+        .add_startup_system(systems::start)
+        .add_startup_system(logging::setup_timing_diagnostics)
+        .add_startup_system(game::animation::setup_animations)
+        .add_asset::<SpriteAnimations>()
+        .add_asset_loader(AsepriteLoader)
+        .add_event::<AnimationFinished>()
+        .insert_resource(DebugOverlay::default())
+        .init_resource::<MapScreen>()
+        .insert_resource(ClearColor(Color::rgb(0.1, 0.1, 0.1)))
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy::prelude::*;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+/// Used when `RUST_LOG` isn't set. `RUST_LOG=game_jam=debug` also prints how long
+/// every timed system took, see `record_time`.
+pub const DEFAULT_LOG_FILTER: &str = "info,wgpu=error,naga=warn";
+
+pub const WALL_COLLISION_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x5a1e_0e53_4d7c_4b5e_9a3f_21c4_7e10_b001);
+pub const PATHFINDING_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x5a1e_0e53_4d7c_4b5e_9a3f_21c4_7e10_b002);
+
+/// Replaces bevy's `LogPlugin`, which has to be disabled for this to work.
+/// Everything goes to stdout, and to `file` as well when there is one.
+pub fn init_logging(file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let file_layer = match file {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_writer(Mutex::new(File::create(path)?)),
+            )
+        }
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        // Closing a span logs how long it was busy for
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE))
+        .with(file_layer)
+        .try_init()?;
+    Ok(())
+}
+
+/// Registers the timings shown by the debug overlay
+pub fn setup_timing_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics
+        .add(Diagnostic::new(WALL_COLLISION_TIME, "rebuild_wall_chunks", 20).with_suffix("ms"));
+    diagnostics.add(Diagnostic::new(PATHFINDING_TIME, "pathfinding", 20).with_suffix("ms"));
+}
+
+/// Adds the time since `start` to a diagnostic. Headless apps have no `Diagnostics`,
+/// they only get the span timings.
+pub fn record_time(
+    diagnostics: &mut Option<ResMut<Diagnostics>>,
+    id: DiagnosticId,
+    start: Instant,
+) {
+    if let Some(diagnostics) = diagnostics {
+        diagnostics.add_measurement(id, start.elapsed().as_secs_f64() * 1000.0);
+    }
+}
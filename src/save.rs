@@ -0,0 +1,55 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Where the game keeps its save data
+pub const SAVE_PATH: &str = "saves/save.ron";
+
+/// Progress that outlives a session
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SaveData {
+    /// Iids of every level the player has been in, shown on the map screen
+    pub explored: BTreeSet<String>,
+    /// Iids of the collectibles picked up
+    pub collected: BTreeSet<String>,
+    /// Iids of the levels finished, shown on the level select screen
+    pub completed: BTreeSet<String>,
+}
+
+impl SaveData {
+    pub fn load(path: impl AsRef<Path>) -> Result<SaveData, Box<dyn Error>> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Where `SaveData` is written whenever it changes, None keeps it in memory only
+#[derive(Resource, Default)]
+pub struct SaveFile(pub Option<PathBuf>);
+
+pub fn write_save(save: Res<SaveData>, save_file: Res<SaveFile>) {
+    if !save.is_changed() || save.is_added() {
+        return;
+    }
+    if let Some(path) = &save_file.0 {
+        match save.save(path) {
+            Ok(()) => debug!(path = %path.display(), "Saved"),
+            Err(error) => error!(path = %path.display(), "Couldn't save: {error}"),
+        }
+    }
+}
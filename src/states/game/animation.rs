@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::components::Player;
+use crate::states::game::components::{Enemy, ItemTag, Items, MainEnemy, Target};
+use crate::states::game::lighting::Illumination;
+use crate::states::game::objectives::{Objective, ObjectiveState};
+
+/// Below this the player is sneaking through the shadows rather than walking
+const SNEAK_ILLUMINATION: f32 = 0.3;
+/// Slower than this counts as standing still
+const MOVING_SPEED: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Clip {
+    Idle,
+    Walk,
+    Sneak,
+    Throw,
+    Alert,
+    Caught,
+}
+
+impl Clip {
+    pub const ALL: [Clip; 6] = [
+        Clip::Idle,
+        Clip::Walk,
+        Clip::Sneak,
+        Clip::Throw,
+        Clip::Alert,
+        Clip::Caught,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Clip::Idle => "idle",
+            Clip::Walk => "walk",
+            Clip::Sneak => "sneak",
+            Clip::Throw => "throw",
+            Clip::Alert => "alert",
+            Clip::Caught => "caught",
+        }
+    }
+
+    /// How the clip plays unless its sprite sheet says otherwise
+    pub fn mode(&self) -> PlayMode {
+        match self {
+            Clip::Idle | Clip::Walk | Clip::Sneak => PlayMode::Loop,
+            Clip::Throw | Clip::Alert | Clip::Caught => PlayMode::Once,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayMode {
+    Loop,
+    /// Stops on the last frame and sends `AnimationFinished`
+    Once,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationFrame {
+    /// Into the texture atlas
+    pub index: usize,
+    /// Seconds
+    pub duration: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlayMode,
+}
+
+/// A sprite sheet and the clips cut out of it, loaded from an Aseprite file by `AsepriteLoader`
+#[derive(TypeUuid, Clone, Debug, Default)]
+#[uuid = "2f1b7c43-9e0d-4a6b-8c55-13d7a6e4f902"]
+pub struct SpriteAnimations {
+    pub atlas: Handle<TextureAtlas>,
+    pub clips: HashMap<Clip, AnimationClip>,
+}
+
+/// The animations of everything that has them
+#[derive(Resource)]
+pub struct AnimationLibrary {
+    pub player: Handle<SpriteAnimations>,
+    pub main_enemy: Handle<SpriteAnimations>,
+    pub glass_bottle: Handle<SpriteAnimations>,
+}
+
+/// Plays clips of `animations` on the entity's `TextureAtlasSprite`
+#[derive(Component)]
+pub struct SpriteAnimator {
+    pub animations: Handle<SpriteAnimations>,
+    pub clip: Clip,
+    pub frame: usize,
+    /// Seconds into the current frame
+    pub elapsed: f32,
+    /// A one-shot clip ran out, the automatic clip choice can take over again
+    pub finished: bool,
+}
+
+impl SpriteAnimator {
+    pub fn new(animations: Handle<SpriteAnimations>) -> Self {
+        SpriteAnimator {
+            animations,
+            clip: Clip::Idle,
+            frame: 0,
+            elapsed: 0.0,
+            finished: false,
+        }
+    }
+
+    /// Starts `clip` from its first frame, unless it is already playing
+    pub fn play(&mut self, clip: Clip) {
+        if self.clip != clip {
+            self.clip = clip;
+            self.frame = 0;
+            self.elapsed = 0.0;
+            self.finished = false;
+        }
+    }
+
+    /// Switches clip, except while a one-shot clip is still playing
+    fn choose(&mut self, clip: Clip, animations: &Assets<SpriteAnimations>) {
+        let playing_once = animations
+            .get(&self.animations)
+            .and_then(|animations| animations.clips.get(&self.clip))
+            .map_or(false, |current| current.mode == PlayMode::Once);
+        if !playing_once || self.finished {
+            self.play(clip);
+        }
+    }
+}
+
+/// Sent when a `PlayMode::Once` clip reaches its end
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: Clip,
+}
+
+pub fn setup_animations(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AnimationLibrary {
+        player: asset_server.load("player.aseprite"),
+        main_enemy: asset_server.load("main_enemy.aseprite"),
+        glass_bottle: asset_server.load("glass_bottle.aseprite"),
+    });
+}
+
+/// Swaps the plain sprite LDtk gave an entity for an animated one, once the animations are loaded
+fn make_animated(
+    commands: &mut Commands,
+    entity: Entity,
+    animations: &Handle<SpriteAnimations>,
+    library: &Assets<SpriteAnimations>,
+) {
+    let Some(sprite_animations) = library.get(animations) else {
+        return;
+    };
+    commands
+        .entity(entity)
+        .remove::<(Sprite, Handle<Image>)>()
+        .insert((
+            TextureAtlasSprite::default(),
+            sprite_animations.atlas.clone(),
+            SpriteAnimator::new(animations.clone()),
+        ));
+}
+
+pub fn attach_animations(
+    mut commands: Commands,
+    library: Res<AnimationLibrary>,
+    animations: Res<Assets<SpriteAnimations>>,
+    player_query: Query<Entity, (With<Player>, Without<ItemTag>, Without<SpriteAnimator>)>,
+    enemy_query: Query<Entity, (With<MainEnemy>, Without<SpriteAnimator>)>,
+    item_query: Query<(Entity, &Items), (With<ItemTag>, Without<Player>, Without<SpriteAnimator>)>,
+) {
+    for player in &player_query {
+        make_animated(&mut commands, player, &library.player, &animations);
+    }
+    for enemy in &enemy_query {
+        make_animated(&mut commands, enemy, &library.main_enemy, &animations);
+    }
+    for (item, kind) in &item_query {
+        if *kind == Items::GlassBottle {
+            make_animated(&mut commands, item, &library.glass_bottle, &animations);
+        }
+    }
+}
+
+/// Caught once detected, otherwise walking or sneaking depending on how lit the player is.
+/// Nothing can be thrown yet, so `Clip::Throw` is only played by hand.
+pub fn choose_player_clip(
+    mut player_query: Query<(&Velocity, &Illumination, &mut SpriteAnimator), With<Player>>,
+    objective_query: Query<(&Objective, &ObjectiveState)>,
+    animations: Res<Assets<SpriteAnimations>>,
+) {
+    let caught = objective_query.iter().any(|(objective, state)| {
+        *objective == Objective::AvoidDetection && *state == ObjectiveState::Failed
+    });
+    for (velocity, illumination, mut animator) in &mut player_query {
+        let clip = if caught {
+            Clip::Caught
+        } else if velocity.linvel.length() < MOVING_SPEED {
+            Clip::Idle
+        } else if illumination.0 < SNEAK_ILLUMINATION {
+            Clip::Sneak
+        } else {
+            Clip::Walk
+        };
+        animator.choose(clip, &animations);
+    }
+}
+
+/// Alert when an enemy hears something, then walking or idle
+pub fn choose_enemy_clip(
+    mut enemy_query: Query<
+        (
+            &Velocity,
+            &Target,
+            ChangeTrackers<Target>,
+            &mut SpriteAnimator,
+        ),
+        With<Enemy>,
+    >,
+    animations: Res<Assets<SpriteAnimations>>,
+) {
+    for (velocity, target, target_changes, mut animator) in &mut enemy_query {
+        // Losing the target doesn't alert anyone
+        if target_changes.is_changed() && !target_changes.is_added() && target.0.is_some() {
+            animator.play(Clip::Alert);
+            continue;
+        }
+        let clip = if velocity.linvel.length() < MOVING_SPEED {
+            Clip::Idle
+        } else {
+            Clip::Walk
+        };
+        animator.choose(clip, &animations);
+    }
+}
+
+pub fn animate_sprites(
+    mut animated_query: Query<(Entity, &mut SpriteAnimator, &mut TextureAtlasSprite)>,
+    animations: Res<Assets<SpriteAnimations>>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    time: Res<Time>,
+) {
+    for (entity, mut animator, mut sprite) in &mut animated_query {
+        let Some(clip) = animations
+            .get(&animator.animations)
+            .and_then(|animations| animations.clips.get(&animator.clip))
+        else {
+            continue;
+        };
+        // Re-exporting the sprite sheet can leave the clip shorter than the frame it was on
+        if animator.frame >= clip.frames.len() {
+            animator.frame = 0;
+            animator.elapsed = 0.0;
+        }
+        if clip.frames.is_empty() || animator.finished {
+            continue;
+        }
+        animator.elapsed += time.delta_seconds();
+        while animator.elapsed >= clip.frames[animator.frame].duration {
+            animator.elapsed -= clip.frames[animator.frame].duration;
+            if animator.frame + 1 < clip.frames.len() {
+                animator.frame += 1;
+            } else if clip.mode == PlayMode::Loop {
+                animator.frame = 0;
+            } else {
+                animator.finished = true;
+                finished_events.send(AnimationFinished {
+                    entity,
+                    clip: animator.clip,
+                });
+                break;
+            }
+        }
+        sprite.index = clip.frames[animator.frame].index;
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use asefile::{AnimationDirection, AsepriteFile};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::BoxedFuture,
+};
+
+use crate::states::game::animation::{AnimationClip, AnimationFrame, Clip, SpriteAnimations};
+
+/// Loads an Aseprite file as `SpriteAnimations`, so the art doesn't have to be exported by hand.
+/// The frames are laid out left to right in the "image" and "atlas" sub-assets,
+/// and every tag named after a `Clip` becomes that clip.
+/// Clips without a tag play the "idle" tag, or every frame if there is none.
+#[derive(Default)]
+pub struct AsepriteLoader;
+
+impl AssetLoader for AsepriteLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let aseprite = AsepriteFile::read(bytes)?;
+            let (width, height) = (aseprite.width(), aseprite.height());
+            let frame_count = aseprite.num_frames();
+
+            let mut data = vec![0; width * height * frame_count as usize * 4];
+            let row_length = width * frame_count as usize * 4;
+            for frame in 0..frame_count {
+                let image = aseprite.frame(frame).image();
+                for (y, row) in image.as_raw().chunks(width * 4).enumerate() {
+                    let start = y * row_length + frame as usize * width * 4;
+                    data[start..start + width * 4].copy_from_slice(row);
+                }
+            }
+            let image = load_context.set_labeled_asset(
+                "image",
+                LoadedAsset::new(Image::new(
+                    Extent3d {
+                        width: (width * frame_count as usize) as u32,
+                        height: height as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rgba8UnormSrgb,
+                )),
+            );
+            let atlas = load_context.set_labeled_asset(
+                "atlas",
+                LoadedAsset::new(TextureAtlas::from_grid(
+                    image,
+                    Vec2::new(width as f32, height as f32),
+                    frame_count as usize,
+                    1,
+                    None,
+                    None,
+                )),
+            );
+
+            let frame = |index: u32| AnimationFrame {
+                index: index as usize,
+                duration: aseprite.frame(index).duration() as f32 / 1000.0,
+            };
+            let mut tags = HashMap::new();
+            for tag in (0..aseprite.num_tags()).map(|index| aseprite.tag(index)) {
+                let forward: Vec<AnimationFrame> =
+                    (tag.from_frame()..=tag.to_frame()).map(&frame).collect();
+                let frames = match tag.animation_direction() {
+                    AnimationDirection::Reverse => forward.into_iter().rev().collect(),
+                    // There and back without playing either end twice
+                    AnimationDirection::PingPong => {
+                        let back = forward.len().saturating_sub(1);
+                        let returning: Vec<_> =
+                            forward[1..back.max(1)].iter().rev().copied().collect();
+                        forward.into_iter().chain(returning).collect()
+                    }
+                    _ => forward,
+                };
+                tags.insert(tag.name().to_string(), frames);
+            }
+            let all_frames: Vec<AnimationFrame> = (0..frame_count).map(&frame).collect();
+            let fallback = tags.get(Clip::Idle.name()).unwrap_or(&all_frames).clone();
+
+            let clips = Clip::ALL
+                .into_iter()
+                .map(|clip| {
+                    let frames = tags.get(clip.name()).unwrap_or(&fallback).clone();
+                    (
+                        clip,
+                        AnimationClip {
+                            frames,
+                            mode: clip.mode(),
+                        },
+                    )
+                })
+                .collect();
+            load_context.set_default_asset(LoadedAsset::new(SpriteAnimations { atlas, clips }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite", "ase"]
+    }
+}
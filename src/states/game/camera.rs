@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy_ecs_ldtk::{LdtkLevel, LevelSelection};
+
+use crate::components::Player;
+use crate::states::game::components::{
+    CameraTag, ItemTag, NoiseEvent, WorldMouseCoords, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use crate::states::game::replay::GameRng;
+use crate::states::game::transitions::{RoomTransition, TransitionStyle};
+use crate::tuning::Tuning;
+
+/// Noises this loud or louder shake the camera as hard as it goes
+const LOUDEST_NOISE: f32 = 200.0;
+
+/// Camera shake that builds up from gameplay events and wears off over time.
+/// Lives in the gameplay so headless systems can add to it too.
+#[derive(Resource, Default)]
+pub struct ScreenShake {
+    /// Between 0 and 1, the shake grows with its square
+    pub trauma: f32,
+}
+
+impl ScreenShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+}
+
+/// Where the camera is looking, before level clamping and shake
+#[derive(Component, Default)]
+pub struct CameraFollow {
+    /// None until the camera first finds the player, so it starts on them instead of sliding over
+    pub focus: Option<Vec2>,
+    /// Where the camera was when the current `RoomTransition` started
+    pub transition_from: Option<Vec2>,
+}
+
+/// How far `target` is outside a `deadzone` sized box around `focus`
+fn outside_deadzone(focus: Vec2, target: Vec2, deadzone: Vec2) -> Vec2 {
+    let offset = target - focus;
+    offset - offset.clamp(-deadzone / 2.0, deadzone / 2.0)
+}
+
+/// Follows the player with damping, a deadzone and look-ahead toward the aim,
+/// kept inside the current level and snapped to the pixels of the render image
+#[allow(clippy::too_many_arguments)]
+pub fn follow_player(
+    mut camera_query: Query<
+        (
+            &mut OrthographicProjection,
+            &mut Transform,
+            &mut CameraFollow,
+        ),
+        (Without<Player>, With<CameraTag>),
+    >,
+    player_query: Query<&Transform, (With<Player>, Without<ItemTag>)>,
+    level_query: Query<
+        (&Transform, &Handle<LdtkLevel>),
+        (Without<OrthographicProjection>, Without<Player>),
+    >,
+    level_selection: Res<LevelSelection>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    world_coords: Res<WorldMouseCoords>,
+    transition: Option<Res<RoomTransition>>,
+    mut shake: ResMut<ScreenShake>,
+    mut rng: ResMut<GameRng>,
+    tuning: Res<Tuning>,
+    time: Res<Time>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let Ok((mut projection, mut camera_transform, mut follow)) = camera_query.get_single_mut()
+    else {
+        return;
+    };
+    let follow = &mut *follow;
+    let Some((level_transform, level)) =
+        level_query
+            .iter()
+            .find_map(|(level_transform, level_handle)| {
+                ldtk_levels
+                    .get(level_handle)
+                    .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+                    .map(|ldtk_level| (level_transform, &ldtk_level.level))
+            })
+    else {
+        return;
+    };
+
+    projection.scaling_mode = ScalingMode::None;
+    projection.left = 0.0;
+    projection.bottom = 0.0;
+    projection.right = SCREEN_WIDTH as f32;
+    projection.top = SCREEN_HEIGHT as f32;
+    let view_size = Vec2::new(projection.right, projection.top);
+
+    // The aim only gives a direction, the cursor moves with the camera so its distance would feed back
+    let player_position = player_transform.translation.truncate();
+    let aim = (world_coords.0 - player_position).normalize_or_zero();
+    let target = player_position + aim * tuning.camera_look_ahead;
+
+    let focus = follow.focus.get_or_insert(target);
+    let dt = time.delta_seconds();
+    if transition.is_some() {
+        // Settled on the new room, so nothing drifts once the transition is over
+        *focus = target;
+    } else {
+        let wanted = *focus + outside_deadzone(*focus, target, tuning.camera_deadzone);
+        *focus = focus.lerp(wanted, 1.0 - (-tuning.camera_damping * dt).exp());
+    }
+
+    let level_origin = level_transform.translation.truncate();
+    let level_size = Vec2::new(level.px_wid as f32, level.px_hei as f32);
+    let mut bottom_left = (*focus - view_size / 2.0 - level_origin)
+        .clamp(Vec2::ZERO, (level_size - view_size).max(Vec2::ZERO))
+        + level_origin;
+
+    match transition {
+        Some(transition) => {
+            let from = *follow
+                .transition_from
+                .get_or_insert(camera_transform.translation.truncate());
+            let progress = transition.progress();
+            bottom_left = match transition.style {
+                TransitionStyle::Slide => {
+                    from.lerp(bottom_left, progress * progress * (3.0 - 2.0 * progress))
+                }
+                TransitionStyle::Fade if progress < 0.5 => from,
+                _ => bottom_left,
+            };
+        }
+        None => follow.transition_from = None,
+    }
+
+    let shake_strength = shake.trauma * shake.trauma * tuning.camera_shake_offset;
+    let rng = rng.view_rng();
+    let shake_offset = Vec2::new(rng.f32() * 2.0 - 1.0, rng.f32() * 2.0 - 1.0) * shake_strength;
+    shake.trauma = (shake.trauma - tuning.camera_shake_decay * dt).max(0.0);
+
+    let snapped = (bottom_left + shake_offset).round();
+    camera_transform.translation.x = snapped.x;
+    camera_transform.translation.y = snapped.y;
+}
+
+/// Loud noises nearby shake the camera
+pub fn shake_on_noise(
+    mut noise_events: EventReader<NoiseEvent>,
+    player_query: Query<&Transform, (With<Player>, Without<ItemTag>)>,
+    mut shake: ResMut<ScreenShake>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        noise_events.clear();
+        return;
+    };
+    for noise in noise_events.iter().filter(|noise| !noise.footstep) {
+        let distance = player_transform
+            .translation
+            .truncate()
+            .distance(noise.position);
+        if distance <= noise.loudness {
+            shake.add_trauma(0.5 * (noise.loudness / LOUDEST_NOISE).min(1.0));
+        }
+    }
+}
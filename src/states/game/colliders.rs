@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::EntityInstance;
+use bevy_rapier2d::prelude::*;
+
+use crate::components::ColliderBundle;
+use crate::states::game::components::SensorBundle;
+use crate::states::game::ldtk_fields::{bool_field, float_field, string_field};
+
+// Colliders of LDtk entities can be set up with these optional fields:
+// "Collider" is "ball", "box" or "capsule", "ColliderWidth" and "ColliderHeight" are in pixels
+// and default to the entity's size, "Sensor" detects overlaps without pushing anything,
+// and "CollisionGroups" and "CollidesWith" list the groups below, e.g. "player, item".
+
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+pub const ENEMY_GROUP: Group = Group::GROUP_2;
+pub const ITEM_GROUP: Group = Group::GROUP_3;
+
+/// The names designers use for the groups. Terrain and doors are in all of them.
+const GROUP_NAMES: [(&str, Group); 3] = [
+    ("player", PLAYER_GROUP),
+    ("enemy", ENEMY_GROUP),
+    ("item", ITEM_GROUP),
+];
+
+fn groups_from_names(names: &str) -> Group {
+    let mut groups = Group::NONE;
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match GROUP_NAMES
+            .iter()
+            .find(|(group_name, _)| name.eq_ignore_ascii_case(group_name))
+        {
+            Some((_, group)) => groups |= *group,
+            None => warn!(group = name, "Unknown collision group"),
+        }
+    }
+    groups
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColliderShape {
+    #[default]
+    Ball,
+    Box,
+    /// Rounded along its longer side
+    Capsule,
+}
+
+impl ColliderShape {
+    pub fn from_name(name: &str) -> Option<ColliderShape> {
+        match name.trim().to_lowercase().as_str() {
+            "ball" => Some(ColliderShape::Ball),
+            "box" => Some(ColliderShape::Box),
+            "capsule" => Some(ColliderShape::Capsule),
+            _ => None,
+        }
+    }
+}
+
+/// What an entity's collider looks like. `attach_colliders` builds it once, when the entity spawns.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct ColliderSpec {
+    pub shape: ColliderShape,
+    /// Width and height in pixels
+    pub size: Vec2,
+    /// Detects overlaps without a rigid body, instead of pushing things around
+    pub sensor: bool,
+    pub groups: CollisionGroups,
+}
+
+impl Default for ColliderSpec {
+    fn default() -> Self {
+        ColliderSpec::character(16.0, Group::ALL)
+    }
+}
+
+impl ColliderSpec {
+    /// A solid ball that moves around, like the player and enemies
+    pub fn character(diameter: f32, group: Group) -> Self {
+        ColliderSpec {
+            shape: ColliderShape::Ball,
+            size: Vec2::splat(diameter),
+            sensor: false,
+            groups: CollisionGroups::new(group, Group::ALL),
+        }
+    }
+
+    /// A sensor box, like items lying around to be picked up
+    pub fn item(size: Vec2) -> Self {
+        ColliderSpec {
+            shape: ColliderShape::Box,
+            size,
+            sensor: true,
+            groups: CollisionGroups::new(ITEM_GROUP, Group::ALL),
+        }
+    }
+
+    /// Of the smallest circle around the collider
+    pub fn radius(&self) -> f32 {
+        let half = self.size / 2.0;
+        match self.shape {
+            ColliderShape::Ball => half.min_element(),
+            ColliderShape::Box => half.length(),
+            ColliderShape::Capsule => half.max_element(),
+        }
+    }
+
+    pub fn collider(&self) -> Collider {
+        let half = self.size / 2.0;
+        match self.shape {
+            ColliderShape::Ball => Collider::ball(half.min_element()),
+            ColliderShape::Box => Collider::cuboid(half.x, half.y),
+            ColliderShape::Capsule if half.y >= half.x => {
+                Collider::capsule_y(half.y - half.x, half.x)
+            }
+            ColliderShape::Capsule => Collider::capsule_x(half.x - half.y, half.y),
+        }
+    }
+}
+
+impl From<EntityInstance> for ColliderSpec {
+    fn from(entity_instance: EntityInstance) -> ColliderSpec {
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        let defaults = match entity_instance.identifier.as_str() {
+            "GlassBottle" => ColliderSpec::item(size),
+            "MainEnemy" | "Enemy" => ColliderSpec::character(size.min_element(), ENEMY_GROUP),
+            "PlayerStart" => ColliderSpec::character(size.min_element(), PLAYER_GROUP),
+            _ => ColliderSpec::character(size.min_element(), Group::ALL),
+        };
+        let fields = &entity_instance.field_instances;
+        let shape = string_field(fields, "Collider")
+            .and_then(|name| ColliderShape::from_name(&name))
+            .unwrap_or(defaults.shape);
+        let size = Vec2::new(
+            float_field(fields, "ColliderWidth").unwrap_or(size.x),
+            float_field(fields, "ColliderHeight").unwrap_or(size.y),
+        );
+        let groups = CollisionGroups::new(
+            string_field(fields, "CollisionGroups").map_or(defaults.groups.memberships, |names| {
+                groups_from_names(&names)
+            }),
+            string_field(fields, "CollidesWith")
+                .map_or(defaults.groups.filters, |names| groups_from_names(&names)),
+        );
+        ColliderSpec {
+            shape,
+            size,
+            sensor: bool_field(fields, "Sensor").unwrap_or(defaults.sensor),
+            groups,
+        }
+    }
+}
+
+/// Gives everything that spawned with a `ColliderSpec` its collider
+pub fn attach_colliders(
+    mut commands: Commands,
+    spec_query: Query<(Entity, &ColliderSpec), Added<ColliderSpec>>,
+) {
+    for (entity, spec) in &spec_query {
+        let rotation_constraints = LockedAxes::ROTATION_LOCKED;
+        if spec.sensor {
+            commands.entity(entity).insert((
+                SensorBundle {
+                    collider: spec.collider(),
+                    sensor: Sensor,
+                    active_events: ActiveEvents::COLLISION_EVENTS,
+                    rotation_constraints,
+                },
+                spec.groups,
+            ));
+        } else {
+            commands.entity(entity).insert((
+                ColliderBundle {
+                    collider: spec.collider(),
+                    rigid_body: RigidBody::Dynamic,
+                    friction: Friction {
+                        coefficient: 0.0,
+                        combine_rule: CoefficientCombineRule::Min,
+                    },
+                    rotation_constraints,
+                    ..Default::default()
+                },
+                spec.groups,
+            ));
+        }
+    }
+}
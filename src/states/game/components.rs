@@ -4,6 +4,9 @@ use bevy_rapier2d::prelude::{ActiveEvents, Collider, CollidingEntities, LockedAx
 use sark_pathfinding::{AStar, PathMap2d};
 
 use crate::components::Game;
+use crate::states::game::colliders::{ColliderSpec, ENEMY_GROUP};
+use crate::states::game::interaction::Interactable;
+use crate::tuning::Tuning;
 
 #[derive(Component)]
 pub struct CameraTag;
@@ -18,6 +21,27 @@ pub const ASPECT_RATIO: f32 = SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32;
 #[derive(Component)]
 pub struct CursorTag;
 
+/// Which frame of `cursor.png` is shown
+#[derive(Component, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum CursorState {
+    #[default]
+    Default,
+    /// Hovering something that can be picked up
+    Interact,
+    /// Holding something that can be thrown
+    Aim,
+}
+
+impl CursorState {
+    pub fn atlas_index(&self) -> usize {
+        match self {
+            CursorState::Default => 0,
+            CursorState::Interact => 1,
+            CursorState::Aim => 2,
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct WorldMouseCoords(pub Vec2);
 
@@ -29,6 +53,20 @@ pub enum Items {
     Shears,
 }
 
+impl Items {
+    pub fn is_throwable(&self) -> bool {
+        matches!(self, Items::GlassBottle)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Items::None => "nothing",
+            Items::GlassBottle => "bottle",
+            Items::Shears => "shears",
+        }
+    }
+}
+
 #[derive(Component, Clone, Default)]
 pub struct ItemTag;
 
@@ -58,6 +96,10 @@ pub struct GlassBottle {
     #[from_entity_instance]
     pub item: Items,
     #[from_entity_instance]
+    pub interaction: Interactable,
+    #[from_entity_instance]
+    pub collider: ColliderSpec,
+    #[from_entity_instance]
     pub entity_instance: EntityInstance,
 }
 
@@ -73,25 +115,54 @@ impl From<EntityInstance> for Items {
 #[derive(Component, Default)]
 pub struct NoiseValue(f32);
 
+/// A sound made somewhere in the level, enemies within `loudness` pixels hear it
+pub struct NoiseEvent {
+    pub position: Vec2,
+    pub loudness: f32,
+    /// The player's own footsteps, these alert enemies but don't shake the camera
+    pub footstep: bool,
+}
+
 #[derive(Resource)]
 pub struct PathfindingMap {
     pub path_map: PathMap2d,
 }
 #[derive(Resource)]
-pub struct AstarMap {
-    pub astar: AStar<[i32; 2]>,
-}
-#[derive(Resource)]
 pub struct PathInit(pub bool);
 
+/// Shared by every kind of enemy, the AI, hearing and detection systems work on all of them
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Enemy;
 
+/// An enemy's own A* scratch space, so enemies don't share search state
+#[derive(Component, Default)]
+pub struct Pathfinder {
+    astar: Option<AStar<[i32; 2]>>,
+    /// Width and height of the nav grid `astar` was sized for
+    size: [u32; 2],
+}
+
+impl Pathfinder {
+    /// Cleared for a new search, only allocated again when the nav grid changes size
+    pub fn astar(&mut self, size: [u32; 2]) -> &mut AStar<[i32; 2]> {
+        if self.size != size {
+            self.astar = None;
+            self.size = size;
+        }
+        let astar = self.astar.get_or_insert_with(|| {
+            AStar::from_size([size[0].try_into().unwrap(), size[1].try_into().unwrap()])
+        });
+        astar.clear();
+        astar
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct MainEnemy;
 
 #[derive(Default, Bundle, LdtkEntity)]
 pub struct MainEnemyBundle {
+    pub enemy: Enemy,
     pub enemy_tag: MainEnemy,
     #[sprite_bundle("main_enemy.png")]
     #[bundle]
@@ -104,10 +175,29 @@ pub struct MainEnemyBundle {
     pub colliding_entities: CollidingEntities,
     pub current_target: Target,
     pub target_path: TargetPath,
+    pub pathfinder: Pathfinder,
+    #[from_entity_instance]
+    pub collider: ColliderSpec,
+}
+
+impl MainEnemyBundle {
+    pub fn new(translation: Vec3, tuning: &Tuning, asset_server: &AssetServer) -> Self {
+        MainEnemyBundle {
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load("main_enemy.png"),
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            collider: ColliderSpec::character(tuning.collider_radius * 2.0, ENEMY_GROUP),
+            ..default()
+        }
+    }
 }
 
+/// Where an enemy last heard something or saw the player, it goes to look there.
+/// None once it got there and found nothing.
 #[derive(Default, Component)]
-pub struct Target(Vec2);
+pub struct Target(pub Option<Vec2>);
 
 #[derive(Default, Component)]
 pub struct TargetPath(pub Vec<[i32; 2]>);
@@ -0,0 +1,476 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{LdtkAsset, LevelSelection};
+use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode, Velocity};
+use iyes_loopless::state::NextState;
+
+use crate::components::{Game, GameState, InGame, Player};
+use crate::states::game::colliders::ColliderSpec;
+use crate::states::game::components::{GlassBottle, ItemTag, Items, MainEnemyBundle};
+use crate::states::game::interaction::Interactable;
+use crate::states::game::replay::FIXED_TIMESTEP_DT;
+use crate::tuning::Tuning;
+
+/// Lines of output kept for the console panel
+const OUTPUT_LINES: usize = 8;
+
+/// Runs a command with the words typed after its name, the `Ok` or `Err` text is printed to the console
+pub type ConsoleCommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+#[derive(Clone)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    /// Shown by `help`, e.g. "tp <x> <y>"
+    pub usage: &'static str,
+    /// Offered by tab completion for the first argument
+    pub arguments: &'static [&'static str],
+    pub run: ConsoleCommandFn,
+}
+
+/// Every command the console knows, by name. Add to it with `register_console_command`.
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(pub BTreeMap<&'static str, ConsoleCommand>);
+
+pub trait RegisterConsoleCommand {
+    fn register_console_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl RegisterConsoleCommand for App {
+    fn register_console_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.init_resource::<ConsoleCommands>();
+        self.world
+            .resource_mut::<ConsoleCommands>()
+            .0
+            .insert(command.name, command);
+        self
+    }
+}
+
+/// The drop-down console, opened with the key left of 1
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    pub output: Vec<String>,
+    pub history: Vec<String>,
+    /// The history entry being edited, None while typing a new line
+    pub history_index: Option<usize>,
+    /// Submitted lines `run_console_commands` hasn't run yet
+    pub pending: Vec<String>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+        if self.output.len() > OUTPUT_LINES {
+            self.output.remove(0);
+        }
+    }
+
+    pub fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.history_index = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.print(format!("> {line}"));
+        self.pending.push(line);
+    }
+
+    pub fn previous_in_history(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None if !self.history.is_empty() => self.history.len() - 1,
+            None => return,
+        };
+        self.history_index = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    pub fn next_in_history(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => {
+                self.history_index = Some(index + 1);
+                self.input = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.history_index = None;
+                self.input.clear();
+            }
+            None => {}
+        }
+    }
+
+    /// Completes the command name, or its first argument, as far as every match agrees
+    pub fn complete(&mut self, commands: &ConsoleCommands) {
+        let words: Vec<&str> = self.input.split(' ').collect();
+        let (typed, candidates): (&str, Vec<&str>) = match words.as_slice() {
+            [name] => (*name, commands.0.keys().copied().collect()),
+            [name, argument] => match commands.0.get(*name) {
+                Some(command) => (*argument, command.arguments.to_vec()),
+                None => return,
+            },
+            _ => return,
+        };
+        let matches: Vec<&str> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(typed))
+            .collect();
+        let Some(first) = matches.first() else {
+            return;
+        };
+        let mut completed = first.to_string();
+        for candidate in &matches[1..] {
+            while !candidate.starts_with(completed.as_str()) {
+                completed.pop();
+            }
+        }
+        if matches.len() == 1 {
+            completed.push(' ');
+        }
+        let prefix_length = self.input.len() - typed.len();
+        self.input.truncate(prefix_length);
+        self.input.push_str(&completed);
+    }
+}
+
+/// Cheats toggled from the console
+#[derive(Resource, Default)]
+pub struct Cheats {
+    /// Enemies can't detect the player
+    pub god: bool,
+    /// Enemies stand still
+    pub ai_off: bool,
+}
+
+pub fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    if pending.is_empty() {
+        return;
+    }
+    for line in pending {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, arguments)) = words.split_first() else {
+            continue;
+        };
+        let command = world.resource::<ConsoleCommands>().0.get(*name).cloned();
+        let result = match command {
+            Some(command) => (command.run)(world, arguments),
+            None => Err(format!("Unknown command {name}, try help")),
+        };
+        let output = match result {
+            Ok(output) => output,
+            Err(error) => error,
+        };
+        if !output.is_empty() {
+            world.resource_mut::<Console>().print(output);
+        }
+    }
+}
+
+fn find_player(world: &mut World) -> Result<Entity, String> {
+    world
+        .query_filtered::<Entity, (With<Player>, Without<ItemTag>)>()
+        .get_single(world)
+        .map_err(|_| "There is no player".to_string())
+}
+
+fn parse<T: std::str::FromStr>(argument: Option<&&str>, usage: &str) -> Result<T, String> {
+    argument
+        .and_then(|argument| argument.parse().ok())
+        .ok_or_else(|| format!("Usage: {usage}"))
+}
+
+pub const HELP: ConsoleCommand = ConsoleCommand {
+    name: "help",
+    usage: "help",
+    arguments: &[],
+    run: help,
+};
+
+fn help(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let commands = world.resource::<ConsoleCommands>();
+    Ok(commands
+        .0
+        .values()
+        .map(|command| command.usage)
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+pub const TP: ConsoleCommand = ConsoleCommand {
+    name: "tp",
+    usage: "tp <x> <y>",
+    arguments: &[],
+    run: tp,
+};
+
+fn tp(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let x: f32 = parse(arguments.first(), TP.usage)?;
+    let y: f32 = parse(arguments.get(1), TP.usage)?;
+    let player = find_player(world)?;
+    let mut player = world.entity_mut(player);
+    if let Some(mut transform) = player.get_mut::<Transform>() {
+        transform.translation.x = x;
+        transform.translation.y = y;
+    }
+    if let Some(mut velocity) = player.get_mut::<Velocity>() {
+        *velocity = Velocity::zero();
+    }
+    Ok(format!("Teleported to {x} {y}"))
+}
+
+pub const LEVEL: ConsoleCommand = ConsoleCommand {
+    name: "level",
+    usage: "level <iid or identifier>",
+    arguments: &[],
+    run: level,
+};
+
+fn level(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let wanted = arguments
+        .first()
+        .copied()
+        .ok_or_else(|| format!("Usage: {}", LEVEL.usage))?;
+    let level = world
+        .resource::<Assets<LdtkAsset>>()
+        .iter()
+        .flat_map(|(_, ldtk_asset)| ldtk_asset.iter_levels())
+        .find(|level| level.iid == wanted || level.identifier == wanted)
+        .map(|level| level.iid.clone())
+        .ok_or_else(|| format!("There is no level {wanted}"))?;
+    // Restarts the game state like `continue_from_results`, so the player spawns at the start
+    world.insert_resource(LevelSelection::Iid(level));
+    world.resource_mut::<InGame>().0 = false;
+    world.insert_resource(NextState(GameState::Game));
+    Ok(format!("Loading {wanted}"))
+}
+
+pub const GIVE: ConsoleCommand = ConsoleCommand {
+    name: "give",
+    usage: "give <bottle|shears|nothing>",
+    arguments: &["bottle", "shears", "nothing"],
+    run: give,
+};
+
+fn give(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let wanted = arguments.first().copied().unwrap_or_default();
+    let item = [Items::GlassBottle, Items::Shears, Items::None]
+        .into_iter()
+        .find(|item| item.name() == wanted)
+        .ok_or_else(|| format!("Usage: {}", GIVE.usage))?;
+    let player = find_player(world)?;
+    world.entity_mut(player).insert(item);
+    Ok(format!("Holding {}", item.name()))
+}
+
+pub const SPAWN: ConsoleCommand = ConsoleCommand {
+    name: "spawn",
+    usage: "spawn <bottle|enemy>",
+    arguments: &["bottle", "enemy"],
+    run: spawn,
+};
+
+fn spawn(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let player = find_player(world)?;
+    let translation = world.get::<Transform>(player).unwrap().translation;
+    let asset_server = world.resource::<AssetServer>().clone();
+    let tuning = world.resource::<Tuning>().clone();
+    match arguments.first().copied() {
+        Some("bottle") => {
+            // Tagged with `Game` through its `InteractableItem`
+            world.spawn(GlassBottle {
+                sprite_bundle: SpriteBundle {
+                    transform: Transform::from_translation(translation),
+                    texture: asset_server.load("glass_bottle.png"),
+                    ..default()
+                },
+                item: Items::GlassBottle,
+                interaction: Interactable::pick_up(Items::GlassBottle),
+                collider: ColliderSpec::item(tuning.item_sensor_half_size * 2.0),
+                ..default()
+            });
+        }
+        Some("enemy") => {
+            world.spawn(MainEnemyBundle::new(translation, &tuning, &asset_server));
+        }
+        _ => return Err(format!("Usage: {}", SPAWN.usage)),
+    }
+    Ok(format!("Spawned {}", arguments[0]))
+}
+
+pub const GOD: ConsoleCommand = ConsoleCommand {
+    name: "god",
+    usage: "god",
+    arguments: &[],
+    run: god,
+};
+
+fn god(world: &mut World, _arguments: &[&str]) -> Result<String, String> {
+    let mut cheats = world.resource_mut::<Cheats>();
+    cheats.god = !cheats.god;
+    Ok(format!(
+        "God mode {}",
+        if cheats.god { "on" } else { "off" }
+    ))
+}
+
+pub const AI: ConsoleCommand = ConsoleCommand {
+    name: "ai",
+    usage: "ai <on|off>",
+    arguments: &["on", "off"],
+    run: ai,
+};
+
+fn ai(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let ai_off = match arguments.first().copied() {
+        Some("on") => false,
+        Some("off") => true,
+        _ => return Err(format!("Usage: {}", AI.usage)),
+    };
+    world.resource_mut::<Cheats>().ai_off = ai_off;
+    Ok(format!("AI {}", arguments[0]))
+}
+
+pub const TIMESCALE: ConsoleCommand = ConsoleCommand {
+    name: "timescale",
+    usage: "timescale <scale>",
+    arguments: &[],
+    run: timescale,
+};
+
+fn timescale(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let scale: f32 = parse(arguments.first(), TIMESCALE.usage)?;
+    if scale <= 0.0 {
+        return Err("The time scale has to be above 0".to_string());
+    }
+    // Everything moves through physics, so scaling its step slows the whole game down
+    match &mut world.resource_mut::<RapierConfiguration>().timestep_mode {
+        TimestepMode::Variable { time_scale, .. }
+        | TimestepMode::Interpolated { time_scale, .. } => *time_scale = scale,
+        TimestepMode::Fixed { dt, .. } => *dt = FIXED_TIMESTEP_DT * scale,
+    }
+    Ok(format!("Time scale {scale}"))
+}
+
+pub const STATE: ConsoleCommand = ConsoleCommand {
+    name: "state",
+    usage: "state <splashscreen|menu|levelselect|game>",
+    arguments: &["splashscreen", "menu", "levelselect", "game"],
+    run: state,
+};
+
+fn state(world: &mut World, arguments: &[&str]) -> Result<String, String> {
+    let state = match arguments.first().copied() {
+        Some("splashscreen") => GameState::Splashscreen,
+        Some("menu") => GameState::Menu,
+        Some("levelselect") => GameState::LevelSelect,
+        Some("game") => GameState::Game,
+        _ => return Err(format!("Usage: {}", STATE.usage)),
+    };
+    // Same as leaving with Escape, see `systems::input`
+    world.resource_mut::<InGame>().0 = false;
+    world.insert_resource(NextState(state));
+    Ok(String::new())
+}
+
+#[derive(Component)]
+pub struct ConsolePanel;
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+pub fn spawn_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(0.0),
+                        top: Val::Px(0.0),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Auto),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+            ConsolePanel,
+            Game,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ConsoleText,
+            ));
+        });
+}
+
+/// Typing into the open console. Runs before the gameplay systems and takes the keyboard from them.
+pub fn console_input(
+    mut console: ResMut<Console>,
+    commands: Res<ConsoleCommands>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+) {
+    if keys.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        *keys = Input::default();
+    }
+    if !console.open {
+        characters.clear();
+        return;
+    }
+    for character in characters.iter() {
+        if character.char != '`' && !character.char.is_control() {
+            console.input.push(character.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        console.submit();
+    } else if keys.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    } else if keys.just_pressed(KeyCode::Up) {
+        console.previous_in_history();
+    } else if keys.just_pressed(KeyCode::Down) {
+        console.next_in_history();
+    } else if keys.just_pressed(KeyCode::Tab) {
+        console.complete(&commands);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    }
+    *keys = Input::default();
+}
+
+pub fn update_console(
+    console: Res<Console>,
+    mut panel_query: Query<&mut Visibility, With<ConsolePanel>>,
+    mut text_query: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut visibility in &mut panel_query {
+        visibility.is_visible = console.open;
+    }
+    for mut text in &mut text_query {
+        let mut lines = console.output.clone();
+        lines.push(format!("> {}_", console.input));
+        text.sections[0].value = lines.join("\n");
+    }
+}
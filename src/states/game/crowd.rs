@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Velocity;
+use sark_pathfinding::PathMap2d;
+
+use crate::states::game::colliders::ColliderSpec;
+use crate::states::game::components::Enemy;
+use crate::states::game::console::Cheats;
+use crate::states::game::terrain::TerrainMap;
+use crate::tuning::Tuning;
+
+/// The cells an enemy standing on `location` claims: that one and the next cell of its path
+pub fn claimed_cells(location: [i32; 2], path: &[[i32; 2]]) -> impl Iterator<Item = [i32; 2]> {
+    let next = path
+        .iter()
+        .skip_while(|cell| **cell != location)
+        .nth(1)
+        .copied();
+    std::iter::once(location).chain(next)
+}
+
+/// Blocks `cells` on the nav grid for one search, leaving out the ones in `keep_free`.
+/// Returns the cells it blocked, walls and closed doors are left alone so `release_cells`
+/// only reopens what was free before.
+pub fn reserve_cells(
+    path_map: &mut PathMap2d,
+    cells: impl IntoIterator<Item = [i32; 2]>,
+    keep_free: &[[i32; 2]],
+) -> Vec<[i32; 2]> {
+    let (width, height) = (path_map.width() as i32, path_map.height() as i32);
+    let mut reserved = Vec::new();
+    for [x, y] in cells {
+        let in_bounds = x >= 0 && y >= 0 && x < width && y < height;
+        if in_bounds && !keep_free.contains(&[x, y]) && !path_map.is_obstacle([x, y]) {
+            path_map.set_obstacle([x, y], true);
+            reserved.push([x, y]);
+        }
+    }
+    reserved
+}
+
+pub fn release_cells(path_map: &mut PathMap2d, cells: &[[i32; 2]]) {
+    for cell in cells {
+        path_map.set_obstacle(*cell, false);
+    }
+}
+
+/// Away from `other_position`, stronger the closer it is and nothing from `radius` on.
+/// Enemies stacked right on top of each other split along x, `side` picks which way.
+fn separation(position: Vec2, other_position: Vec2, radius: f32, side: f32) -> Vec2 {
+    let offset = position - other_position;
+    let distance = offset.length();
+    if distance >= radius {
+        return Vec2::ZERO;
+    }
+    let away = if distance > f32::EPSILON {
+        offset / distance
+    } else {
+        Vec2::X * side
+    };
+    away * (1.0 - distance / radius)
+}
+
+/// A velocity obstacle check: if neither enemy changed course, would they come closer than
+/// `touching` within the horizon? If so this steers out of the way, harder the sooner
+/// and the more head on the collision is.
+fn sidestep(
+    position: Vec2,
+    velocity: Vec2,
+    other_position: Vec2,
+    other_velocity: Vec2,
+    touching: f32,
+    tuning: &Tuning,
+) -> Vec2 {
+    let offset = other_position - position;
+    let closing = velocity - other_velocity;
+    let closing_speed = closing.length_squared();
+    if closing_speed <= f32::EPSILON {
+        return Vec2::ZERO;
+    }
+    let time = offset.dot(closing) / closing_speed;
+    if time <= 0.0 || time > tuning.enemy_avoidance_horizon {
+        return Vec2::ZERO;
+    }
+    // Where the other enemy ends up relative to this one when they are closest
+    let miss = offset - closing * time;
+    let miss_distance = miss.length();
+    if miss_distance >= touching {
+        return Vec2::ZERO;
+    }
+    // Straight at each other, both turn to their own right so they don't dodge the same way
+    let away = if miss_distance > f32::EPSILON {
+        -miss / miss_distance
+    } else {
+        -closing.perp().normalize()
+    };
+    away * (1.0 - miss_distance / touching) * (1.0 - time / tuning.enemy_avoidance_horizon)
+}
+
+/// Steers enemies around each other on top of the velocity `move_enemies` gave them for their path,
+/// so groups spread out instead of piling into one another
+pub fn avoid_crowding(
+    mut enemy_query: Query<(Entity, &Transform, &ColliderSpec, &mut Velocity), With<Enemy>>,
+    terrain_map: Res<TerrainMap>,
+    cheats: Res<Cheats>,
+    tuning: Res<Tuning>,
+) {
+    if cheats.ai_off {
+        return;
+    }
+    // Everyone steers from the same snapshot, so the order enemies are visited in doesn't matter
+    let crowd: Vec<(Entity, Vec2, Vec2, f32)> = enemy_query
+        .iter()
+        .map(|(entity, transform, collider, velocity)| {
+            (
+                entity,
+                transform.translation.truncate(),
+                velocity.linvel,
+                collider.radius(),
+            )
+        })
+        .collect();
+    if crowd.len() < 2 {
+        return;
+    }
+
+    for (entity, transform, collider, mut velocity) in &mut enemy_query {
+        let position = transform.translation.truncate();
+        let wanted = velocity.linvel;
+        // The same speed `move_enemies` gave them, so no one speeds up over slow terrain
+        let speed = tuning.enemy_speed * terrain_map.speed_at(position);
+        let mut steering = Vec2::ZERO;
+        for &(other, other_position, other_velocity, other_radius) in &crowd {
+            if other == entity {
+                continue;
+            }
+            let side = if entity < other { -1.0 } else { 1.0 };
+            steering += separation(
+                position,
+                other_position,
+                tuning.enemy_separation_radius,
+                side,
+            ) * tuning.enemy_separation_strength;
+            steering += sidestep(
+                position,
+                wanted,
+                other_position,
+                other_velocity,
+                collider.radius() + other_radius,
+                &tuning,
+            );
+        }
+        velocity.linvel = (wanted + steering * speed).clamp_length_max(speed);
+    }
+}
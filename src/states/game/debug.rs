@@ -0,0 +1,362 @@
+use bevy::diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::Type;
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use bevy_ecs_ldtk::{LdtkLevel, LevelSelection};
+use bevy_rapier2d::render::DebugRenderContext;
+
+use crate::components::{Game, Player};
+use crate::logging::{PATHFINDING_TIME, WALL_COLLISION_TIME};
+use crate::states::game::components::{
+    Enemy, ItemTag, NoiseEvent, PathfindingMap, Target, TargetPath,
+};
+use crate::states::game::drawing::{spawn_circle, spawn_line, spawn_rect};
+use crate::states::game::lighting::Illumination;
+use crate::states::game::objectives::detection_range;
+
+/// Above the lighting overlay so darkness doesn't hide anything, below the cursor
+const DEBUG_Z: f32 = 998.95;
+/// How long a noise stays on screen after it is made
+const NOISE_SHOWN_FOR: f32 = 1.0;
+
+const NAV_GRID_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.35);
+const PATH_COLOR: Color = Color::rgb(0.2, 1.0, 0.4);
+const TARGET_COLOR: Color = Color::rgb(1.0, 0.5, 0.0);
+const NOISE_COLOR: Color = Color::rgb(0.3, 0.6, 1.0);
+const VISION_COLOR: Color = Color::rgb(1.0, 1.0, 0.3);
+
+/// F3 shows or hides the whole overlay, F4 to F9 toggle its layers while it is shown
+#[derive(Resource)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    /// Drawn by `RapierDebugRenderPlugin`
+    pub colliders: bool,
+    pub nav_grid: bool,
+    /// Each enemy's `TargetPath` and `Target`
+    pub paths: bool,
+    pub noise: bool,
+    /// How far each enemy can see the player right now, see `detection_range`
+    pub vision: bool,
+    /// FPS, entity count and how long the timed systems took, see `logging::record_time`
+    pub stats: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        DebugOverlay {
+            enabled: false,
+            colliders: true,
+            nav_grid: true,
+            paths: true,
+            noise: true,
+            vision: true,
+            stats: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugLayer {
+    NavGrid,
+    Paths,
+    Noise,
+    Vision,
+}
+
+impl DebugOverlay {
+    pub fn shows(&self, layer: DebugLayer) -> bool {
+        self.enabled
+            && match layer {
+                DebugLayer::NavGrid => self.nav_grid,
+                DebugLayer::Paths => self.paths,
+                DebugLayer::Noise => self.noise,
+                DebugLayer::Vision => self.vision,
+            }
+    }
+}
+
+/// Part of the overlay, respawned by the system drawing its layer
+#[derive(Component, Clone)]
+pub struct DebugShape(pub DebugLayer);
+
+#[derive(Component)]
+pub struct DebugStats;
+
+pub fn toggle_debug_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    for key in keys.get_just_pressed() {
+        match key {
+            KeyCode::F3 => overlay.enabled = !overlay.enabled,
+            KeyCode::F4 if overlay.enabled => overlay.colliders = !overlay.colliders,
+            KeyCode::F5 if overlay.enabled => overlay.nav_grid = !overlay.nav_grid,
+            KeyCode::F6 if overlay.enabled => overlay.paths = !overlay.paths,
+            KeyCode::F7 if overlay.enabled => overlay.noise = !overlay.noise,
+            KeyCode::F8 if overlay.enabled => overlay.vision = !overlay.vision,
+            KeyCode::F9 if overlay.enabled => overlay.stats = !overlay.stats,
+            _ => {}
+        }
+    }
+}
+
+pub fn toggle_collider_debug(
+    overlay: Res<DebugOverlay>,
+    mut debug_render_context: ResMut<DebugRenderContext>,
+) {
+    if overlay.is_changed() {
+        debug_render_context.enabled = overlay.enabled && overlay.colliders;
+    }
+}
+
+fn clear_layer(
+    commands: &mut Commands,
+    shape_query: &Query<(Entity, &DebugShape)>,
+    layer: DebugLayer,
+) {
+    for (entity, shape) in shape_query {
+        if shape.0 == layer {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Where the nav grid of the selected level is laid out
+#[derive(Clone, Copy)]
+struct LevelGrid {
+    /// Bottom left corner of the level
+    origin: Vec3,
+    /// Of the IntGrid layer the nav grid is built from
+    grid_size: f32,
+}
+
+impl LevelGrid {
+    fn find(
+        level_query: &Query<(Entity, &Handle<LdtkLevel>, &Transform)>,
+        layer_query: &Query<(&LayerMetadata, &Parent)>,
+        ldtk_levels: &Assets<LdtkLevel>,
+        level_selection: &LevelSelection,
+    ) -> Option<LevelGrid> {
+        let (level_entity, _, level_transform) =
+            level_query.iter().find(|(_, level_handle, _)| {
+                ldtk_levels.get(level_handle).map_or(false, |ldtk_level| {
+                    level_selection.is_match(&0, &ldtk_level.level)
+                })
+            })?;
+        let (layer, _) = layer_query.iter().find(|(layer, level)| {
+            level.get() == level_entity && layer.layer_instance_type == Type::IntGrid
+        })?;
+        Some(LevelGrid {
+            origin: level_transform.translation,
+            grid_size: layer.grid_size as f32,
+        })
+    }
+
+    fn cell_center(&self, cell: [i32; 2]) -> Vec3 {
+        Vec3::new(
+            self.origin.x + (cell[0] as f32 + 0.5) * self.grid_size,
+            self.origin.y + (cell[1] as f32 + 0.5) * self.grid_size,
+            DEBUG_Z,
+        )
+    }
+}
+
+/// Only redrawn when the nav grid changes, the enemies' searches don't count,
+/// see `move_enemies`
+#[allow(clippy::too_many_arguments)]
+pub fn draw_nav_grid(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    shape_query: Query<(Entity, &DebugShape)>,
+    path_map: Res<PathfindingMap>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>, &Transform)>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+) {
+    if !path_map.is_changed() && !overlay.is_changed() && !level_selection.is_changed() {
+        return;
+    }
+    clear_layer(&mut commands, &shape_query, DebugLayer::NavGrid);
+    if !overlay.shows(DebugLayer::NavGrid) {
+        return;
+    }
+    let Some(grid) = LevelGrid::find(&level_query, &layer_query, &ldtk_levels, &level_selection)
+    else {
+        return;
+    };
+    for x in 0..path_map.path_map.width() as i32 {
+        for y in 0..path_map.path_map.height() as i32 {
+            if path_map.path_map.is_obstacle([x, y]) {
+                spawn_rect(
+                    &mut commands,
+                    (Game, DebugShape(DebugLayer::NavGrid)),
+                    grid.cell_center([x, y]),
+                    Vec2::splat(grid.grid_size - 2.0),
+                    NAV_GRID_COLOR,
+                );
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn draw_enemy_paths(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    shape_query: Query<(Entity, &DebugShape)>,
+    enemy_query: Query<(&Transform, &TargetPath, &Target), With<Enemy>>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>, &Transform)>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+) {
+    clear_layer(&mut commands, &shape_query, DebugLayer::Paths);
+    if !overlay.shows(DebugLayer::Paths) {
+        return;
+    }
+    let Some(grid) = LevelGrid::find(&level_query, &layer_query, &ldtk_levels, &level_selection)
+    else {
+        return;
+    };
+    let shape = (Game, DebugShape(DebugLayer::Paths));
+    for (enemy_transform, path, target) in &enemy_query {
+        for step in path.0.windows(2) {
+            spawn_line(
+                &mut commands,
+                shape.clone(),
+                grid.cell_center(step[0]),
+                grid.cell_center(step[1]),
+                PATH_COLOR,
+            );
+        }
+        if let Some(target) = target.0 {
+            let enemy_position = enemy_transform.translation.truncate().extend(DEBUG_Z);
+            let target_position = target.extend(DEBUG_Z);
+            spawn_line(
+                &mut commands,
+                shape.clone(),
+                enemy_position,
+                target_position,
+                TARGET_COLOR,
+            );
+            spawn_rect(
+                &mut commands,
+                shape.clone(),
+                target_position,
+                Vec2::splat(4.0),
+                TARGET_COLOR,
+            );
+        }
+    }
+}
+
+pub fn draw_noise(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    shape_query: Query<(Entity, &DebugShape)>,
+    mut noise_events: EventReader<NoiseEvent>,
+    mut heard: Local<Vec<(Vec2, f32, f32)>>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    heard.extend(
+        noise_events
+            .iter()
+            .map(|noise| (noise.position, noise.loudness, now)),
+    );
+    heard.retain(|(_, _, made)| now - made < NOISE_SHOWN_FOR);
+
+    clear_layer(&mut commands, &shape_query, DebugLayer::Noise);
+    if !overlay.shows(DebugLayer::Noise) {
+        return;
+    }
+    for (position, loudness, _) in heard.iter() {
+        spawn_circle(
+            &mut commands,
+            (Game, DebugShape(DebugLayer::Noise)),
+            position.extend(DEBUG_Z),
+            *loudness,
+            NOISE_COLOR,
+        );
+    }
+}
+
+pub fn draw_vision(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    shape_query: Query<(Entity, &DebugShape)>,
+    enemy_query: Query<&Transform, With<Enemy>>,
+    player_query: Query<&Illumination, (With<Player>, Without<ItemTag>)>,
+) {
+    clear_layer(&mut commands, &shape_query, DebugLayer::Vision);
+    if !overlay.shows(DebugLayer::Vision) {
+        return;
+    }
+    let illumination = player_query
+        .get_single()
+        .map_or(1.0, |illumination| illumination.0);
+    for enemy_transform in &enemy_query {
+        spawn_circle(
+            &mut commands,
+            (Game, DebugShape(DebugLayer::Vision)),
+            enemy_transform.translation.truncate().extend(DEBUG_Z),
+            detection_range(illumination),
+            VISION_COLOR,
+        );
+    }
+}
+
+pub fn spawn_debug_stats(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                font_size: 16.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(8.0),
+                top: Val::Px(8.0),
+                ..default()
+            },
+            ..default()
+        }),
+        DebugStats,
+        Game,
+    ));
+}
+
+pub fn update_debug_stats(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    mut stats_query: Query<(&mut Text, &mut Visibility), With<DebugStats>>,
+) {
+    let Ok((mut text, mut visibility)) = stats_query.get_single_mut() else {
+        return;
+    };
+    visibility.is_visible = overlay.enabled && overlay.stats;
+    if !visibility.is_visible {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or(0.0);
+    let entities = diagnostics
+        .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|entities| entities.value())
+        .unwrap_or(0.0);
+    let average_ms = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|timing| timing.average())
+            .unwrap_or(0.0)
+    };
+    text.sections[0].value = format!(
+        "FPS {fps:.0}\nEntities {entities:.0}\nWall colliders {:.2}ms\nPathfinding {:.2}ms",
+        average_ms(WALL_COLLISION_TIME),
+        average_ms(PATHFINDING_TIME)
+    );
+}
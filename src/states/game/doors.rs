@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+
+use bevy::ecs::query::ChangeTrackers;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use bevy_ecs_ldtk::{EntityInstance, GridCoords, LdtkEntity, LdtkLevel, LevelSelection};
+use bevy_rapier2d::prelude::{Collider, RigidBody};
+
+use crate::components::Game;
+use crate::states::game::components::{Enemy, NoiseEvent, PathInit, PathfindingMap, TargetPath};
+use crate::states::game::interaction::{InteractEvent, Interactable, InteractionKind};
+use crate::states::game::ldtk_fields::{bool_field, string_field};
+use crate::states::game::terrain::AgentClass;
+use crate::states::game::wall_chunks::TerrainCells;
+
+/// Width and height of door.png and of the Door entity in LDtk,
+/// the transform scales doors up to the size they were placed at
+const DOOR_SIZE: f32 = 16.0;
+/// How far away opening or closing a door can be heard
+const DOOR_NOISE: f32 = 96.0;
+/// Enemies open a door once it is this many steps ahead on their path
+const ENEMY_DOOR_REACH: usize = 2;
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Door {
+    pub open: bool,
+    pub locked: bool,
+    /// Id of the key that unlocks the door, set with the KeyId field in LDtk
+    pub key: Option<String>,
+}
+
+impl Door {
+    pub fn prompt(&self) -> &'static str {
+        if self.locked {
+            "Locked"
+        } else if self.open {
+            "Close door"
+        } else {
+            "Open door"
+        }
+    }
+
+    fn texture(&self) -> &'static str {
+        if self.open {
+            "door_open.png"
+        } else if self.locked {
+            "door_locked.png"
+        } else {
+            "door.png"
+        }
+    }
+}
+
+impl From<EntityInstance> for Door {
+    fn from(entity_instance: EntityInstance) -> Door {
+        let fields = &entity_instance.field_instances;
+        Door {
+            open: bool_field(fields, "Open").unwrap_or(false),
+            locked: bool_field(fields, "Locked").unwrap_or(false),
+            key: string_field(fields, "KeyId"),
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct DoorBundle {
+    #[sprite_bundle("door.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub door: Door,
+    #[from_entity_instance]
+    pub interaction: Interactable,
+    #[with(fixed_body)]
+    pub rigid_body: RigidBody,
+    game: Game,
+}
+
+/// The default rigid body is dynamic, which would let anyone push a closed door off its cells
+fn fixed_body(_: &EntityInstance) -> RigidBody {
+    RigidBody::Fixed
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct Key(pub String);
+
+impl From<EntityInstance> for Key {
+    fn from(entity_instance: EntityInstance) -> Key {
+        Key(string_field(&entity_instance.field_instances, "KeyId").unwrap_or_default())
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct KeyBundle {
+    #[sprite_bundle("key.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub key: Key,
+    #[from_entity_instance]
+    pub interaction: Interactable,
+    game: Game,
+}
+
+/// Ids of the keys the player has picked up, keys don't take up the item slot
+#[derive(Component, Clone, Debug, Default)]
+pub struct KeyRing(pub HashSet<String>);
+
+/// Grid cells covered by a door, doors are children of their level and scaled to their LDtk size
+fn door_cells(transform: &Transform, grid_size: f32) -> Vec<[i32; 2]> {
+    let size = transform.scale.truncate() * DOOR_SIZE;
+    let min = ((transform.translation.truncate() - size / 2.0) / grid_size).round();
+    let cells = (size / grid_size).round().max(Vec2::ONE);
+    let mut covered = Vec::new();
+    for x in 0..cells.x as i32 {
+        for y in 0..cells.y as i32 {
+            covered.push([min.x as i32 + x, min.y as i32 + y]);
+        }
+    }
+    covered
+}
+
+/// The IntGrid layers of `level` that hold terrain, and the size of their cells
+fn terrain_layers(
+    level: Entity,
+    layer_query: &Query<(Entity, &LayerMetadata, &Parent)>,
+    terrain_cells: &TerrainCells,
+) -> (Vec<Entity>, f32) {
+    let mut grid_size = DOOR_SIZE;
+    let mut layers = Vec::new();
+    for (layer_entity, layer, parent) in layer_query {
+        if parent.get() == level && terrain_cells.has_layer(layer_entity) {
+            grid_size = layer.grid_size as f32;
+            layers.push(layer_entity);
+        }
+    }
+    (layers, grid_size)
+}
+
+pub fn use_doors(
+    mut interact_events: EventReader<InteractEvent>,
+    mut door_query: Query<&mut Door>,
+    key_ring_query: Query<&KeyRing>,
+) {
+    for event in interact_events.iter() {
+        if event.kind != InteractionKind::Door {
+            continue;
+        }
+        if let Ok(mut door) = door_query.get_mut(event.target) {
+            if door.locked {
+                let has_key = match (&door.key, key_ring_query.get(event.actor)) {
+                    (Some(key), Ok(key_ring)) => key_ring.0.contains(key),
+                    _ => false,
+                };
+                if has_key {
+                    door.locked = false;
+                    door.open = true;
+                }
+            } else {
+                door.open = !door.open;
+            }
+        }
+    }
+}
+
+pub fn collect_keys(
+    mut commands: Commands,
+    mut interact_events: EventReader<InteractEvent>,
+    key_query: Query<&Key>,
+    mut key_ring_query: Query<&mut KeyRing>,
+) {
+    for event in interact_events.iter() {
+        if event.kind != InteractionKind::PickUp {
+            continue;
+        }
+        if let (Ok(key), Ok(mut key_ring)) = (
+            key_query.get(event.target),
+            key_ring_query.get_mut(event.actor),
+        ) {
+            key_ring.0.insert(key.0.clone());
+            commands.entity(event.target).despawn_recursive();
+        }
+    }
+}
+
+/// Applies door state to the collider, sprite and prompt, and makes noise when it changes
+pub fn update_doors(
+    mut commands: Commands,
+    mut door_query: Query<
+        (
+            Entity,
+            &Door,
+            ChangeTrackers<Door>,
+            &GlobalTransform,
+            &mut Handle<Image>,
+            &mut Interactable,
+        ),
+        Changed<Door>,
+    >,
+    asset_server: Res<AssetServer>,
+    mut noise_events: EventWriter<NoiseEvent>,
+) {
+    for (entity, door, tracker, transform, mut texture, mut interactable) in &mut door_query {
+        if door.open {
+            commands.entity(entity).remove::<Collider>();
+        } else {
+            // Scaled up to the door's size by its transform
+            commands
+                .entity(entity)
+                .insert(Collider::cuboid(DOOR_SIZE / 2.0, DOOR_SIZE / 2.0));
+        }
+        *texture = asset_server.load(door.texture());
+        interactable.prompt = door.prompt().to_string();
+        if !tracker.is_added() {
+            noise_events.send(NoiseEvent {
+                position: transform.translation().truncate(),
+                loudness: DOOR_NOISE,
+                footstep: false,
+            });
+        }
+    }
+}
+
+/// Closed locked doors block pathfinding, unlocked ones don't since enemies can open them.
+/// Terrain under a door that enemies can't walk on stays blocked either way.
+/// Runs when doors change, and when the nav grid is built or patched from the terrain,
+/// since both overwrite the cells doors are on.
+#[allow(clippy::too_many_arguments)]
+pub fn update_door_nav(
+    changed_doors: Query<(), Changed<Door>>,
+    door_query: Query<(&Door, &Transform, &Parent)>,
+    layer_query: Query<(Entity, &LayerMetadata, &Parent)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    terrain_cells: Res<TerrainCells>,
+    path_map_initialized: Res<PathInit>,
+    mut path_map: ResMut<PathfindingMap>,
+) {
+    if changed_doors.is_empty()
+        && !path_map_initialized.is_changed()
+        && !terrain_cells.is_changed()
+        && !level_selection.is_changed()
+    {
+        return;
+    }
+    // The nav grid only covers the current level
+    let Some(current_level) = level_query.iter().find_map(|(level_entity, level_handle)| {
+        ldtk_levels
+            .get(level_handle)
+            .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+            .map(|_| level_entity)
+    }) else {
+        return;
+    };
+    let (layers, grid_size) = terrain_layers(current_level, &layer_query, &terrain_cells);
+    let width = path_map.path_map.width() as i32;
+    let height = path_map.path_map.height() as i32;
+    for (door, transform, level) in &door_query {
+        if level.get() != current_level {
+            continue;
+        }
+        for [x, y] in door_cells(transform, grid_size) {
+            if x >= 0 && y >= 0 && x < width && y < height {
+                let unwalkable = layers.iter().any(|layer| {
+                    terrain_cells
+                        .get(*layer, GridCoords { x, y })
+                        .map_or(false, |terrain| !terrain.walkable_by(AgentClass::Enemy))
+                });
+                path_map
+                    .path_map
+                    .set_obstacle([x, y], (!door.open && door.locked) || unwalkable);
+            }
+        }
+    }
+}
+
+pub fn enemies_open_doors(
+    enemy_query: Query<&TargetPath, With<Enemy>>,
+    mut door_query: Query<(&mut Door, &Transform, &Parent)>,
+    layer_query: Query<(Entity, &LayerMetadata, &Parent)>,
+    terrain_cells: Res<TerrainCells>,
+) {
+    for path in &enemy_query {
+        let ahead: Vec<[i32; 2]> = path.0.iter().take(ENEMY_DOOR_REACH + 1).copied().collect();
+        for (mut door, transform, level) in &mut door_query {
+            if door.open || door.locked {
+                continue;
+            }
+            let (_, grid_size) = terrain_layers(level.get(), &layer_query, &terrain_cells);
+            if door_cells(transform, grid_size)
+                .iter()
+                .any(|cell| ahead.contains(cell))
+            {
+                door.open = true;
+            }
+        }
+    }
+}
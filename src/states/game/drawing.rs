@@ -0,0 +1,79 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+// Debug shapes built out of plain sprites, so they go through the same camera,
+// render image and post processing as everything else in the game view.
+
+const LINE_WIDTH: f32 = 1.0;
+const CIRCLE_SEGMENTS: usize = 24;
+
+pub fn spawn_rect(
+    commands: &mut Commands,
+    bundle: impl Bundle,
+    center: Vec3,
+    size: Vec2,
+    color: Color,
+) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(center),
+            ..default()
+        },
+        bundle,
+    ));
+}
+
+pub fn spawn_line(
+    commands: &mut Commands,
+    bundle: impl Bundle,
+    from: Vec3,
+    to: Vec3,
+    color: Color,
+) {
+    let difference = (to - from).truncate();
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(difference.length(), LINE_WIDTH)),
+                ..default()
+            },
+            transform: Transform {
+                translation: (from + to) / 2.0,
+                rotation: Quat::from_rotation_z(difference.y.atan2(difference.x)),
+                ..default()
+            },
+            ..default()
+        },
+        bundle,
+    ));
+}
+
+/// An outline, made of `CIRCLE_SEGMENTS` lines
+pub fn spawn_circle(
+    commands: &mut Commands,
+    bundle: impl Bundle + Clone,
+    center: Vec3,
+    radius: f32,
+    color: Color,
+) {
+    let point = |segment: usize| {
+        let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+        center + Vec3::new(angle.cos(), angle.sin(), 0.0) * radius
+    };
+    for segment in 0..CIRCLE_SEGMENTS {
+        spawn_line(
+            commands,
+            bundle.clone(),
+            point(segment),
+            point(segment + 1),
+            color,
+        );
+    }
+}
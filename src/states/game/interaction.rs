@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::EntityInstance;
+
+use crate::components::{Game, Player};
+use crate::states::game::components::{CameraTag, ItemTag, Items, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::states::game::doors::Door;
+use crate::states::game::objectives::collectible_name;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InteractionKind {
+    PickUp,
+    Door,
+}
+
+/// Anything the player can use with E. Every frame the highest priority
+/// interactable in range is picked, ties go to the nearest one.
+#[derive(Component, Clone, Debug)]
+pub struct Interactable {
+    pub kind: InteractionKind,
+    /// Shown after "E: " above the target
+    pub prompt: String,
+    pub priority: i32,
+    /// Distance in pixels from the player
+    pub range: f32,
+}
+
+impl Interactable {
+    pub fn pick_up(item: Items) -> Self {
+        Interactable {
+            kind: InteractionKind::PickUp,
+            prompt: format!("Pick up {}", item.name()),
+            priority: 0,
+            range: 16.0,
+        }
+    }
+}
+
+impl Default for Interactable {
+    fn default() -> Self {
+        Interactable::pick_up(Items::None)
+    }
+}
+
+impl From<EntityInstance> for Interactable {
+    fn from(entity_instance: EntityInstance) -> Interactable {
+        match entity_instance.identifier.as_ref() {
+            "Door" => Interactable {
+                kind: InteractionKind::Door,
+                prompt: Door::from(entity_instance.clone()).prompt().to_string(),
+                priority: 1,
+                // Wide doors can be used from anywhere along them
+                range: 16.0 + entity_instance.width.max(entity_instance.height) as f32 / 2.0,
+            },
+            "Collectible" => Interactable {
+                prompt: format!("Pick up {}", collectible_name(&entity_instance)),
+                ..Interactable::pick_up(Items::None)
+            },
+            "Key" => Interactable {
+                prompt: "Pick up key".to_string(),
+                ..Interactable::pick_up(Items::None)
+            },
+            _ => Interactable::pick_up(Items::from(entity_instance)),
+        }
+    }
+}
+
+/// The interactable the player would use by pressing E right now
+#[derive(Resource, Default)]
+pub struct InteractionTarget(pub Option<Entity>);
+
+/// Sent when the player uses an interactable, handled by a system per `InteractionKind`
+pub struct InteractEvent {
+    pub actor: Entity,
+    pub target: Entity,
+    pub kind: InteractionKind,
+}
+
+#[derive(Component)]
+pub struct InteractionPrompt;
+
+const PROMPT_FONT_SIZE: f32 = 16.0;
+
+pub fn spawn_interaction_prompt(commands: &mut Commands, asset_server: &AssetServer) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                font_size: PROMPT_FONT_SIZE,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            ..default()
+        }),
+        InteractionPrompt,
+        Game,
+    ));
+}
+
+pub fn find_interaction_target(
+    player_query: Query<&GlobalTransform, (With<Player>, Without<ItemTag>)>,
+    interactable_query: Query<(Entity, &GlobalTransform, &Interactable)>,
+    mut target: ResMut<InteractionTarget>,
+) {
+    let mut best: Option<(Entity, i32, f32)> = None;
+    if let Ok(player_transform) = player_query.get_single() {
+        let player_position = player_transform.translation().truncate();
+        for (entity, transform, interactable) in &interactable_query {
+            let distance = transform.translation().truncate().distance(player_position);
+            if distance > interactable.range {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some((_, priority, best_distance)) => {
+                    interactable.priority > priority
+                        || (interactable.priority == priority && distance < best_distance)
+                }
+            };
+            if better {
+                best = Some((entity, interactable.priority, distance));
+            }
+        }
+    }
+    let new_target = best.map(|(entity, _, _)| entity);
+    if target.0 != new_target {
+        target.0 = new_target;
+    }
+}
+
+pub fn interact(
+    input: Res<Input<KeyCode>>,
+    target: Res<InteractionTarget>,
+    player_query: Query<Entity, (With<Player>, Without<ItemTag>)>,
+    interactable_query: Query<&Interactable>,
+    mut interact_events: EventWriter<InteractEvent>,
+) {
+    if input.just_pressed(KeyCode::E) {
+        if let (Some(target), Ok(player)) = (target.0, player_query.get_single()) {
+            if let Ok(interactable) = interactable_query.get(target) {
+                interact_events.send(InteractEvent {
+                    actor: player,
+                    target,
+                    kind: interactable.kind,
+                });
+            }
+        }
+    }
+}
+
+/// Converts a world position to ui coordinates, going through the render image
+/// and the viewport `scale_render_image` fits it into
+pub fn world_to_ui(
+    world_position: Vec2,
+    camera_translation: Vec2,
+    window: &Window,
+    viewport_camera: &Camera,
+) -> Option<Vec2> {
+    let viewport = viewport_camera.viewport.as_ref()?;
+    let relative = (world_position - camera_translation)
+        / Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+    let size = viewport.physical_size.as_vec2();
+    let position = viewport.physical_position.as_vec2();
+    let physical = Vec2::new(
+        position.x + relative.x * size.x,
+        window.physical_height() as f32 - position.y - size.y + relative.y * size.y,
+    );
+    Some(physical / window.scale_factor() as f32)
+}
+
+pub fn update_interaction_prompt(
+    target: Res<InteractionTarget>,
+    interactable_query: Query<(&GlobalTransform, &Interactable)>,
+    mut prompt_query: Query<(&mut Text, &mut Style, &mut Visibility), With<InteractionPrompt>>,
+    camera_query: Query<&Transform, With<CameraTag>>,
+    viewport_query: Query<&Camera, Without<CameraTag>>,
+    windows: Res<Windows>,
+) {
+    if let Ok((mut text, mut style, mut visibility)) = prompt_query.get_single_mut() {
+        let target = target
+            .0
+            .and_then(|entity| interactable_query.get(entity).ok());
+        let position = target
+            .zip(windows.get_primary())
+            .and_then(|(target, window)| {
+                let camera_transform = camera_query.get_single().ok()?;
+                let viewport_camera = viewport_query.get_single().ok()?;
+                // Above the target
+                let anchor = target.0.translation().truncate() + Vec2::new(0.0, 12.0);
+                world_to_ui(
+                    anchor,
+                    camera_transform.translation.truncate(),
+                    window,
+                    viewport_camera,
+                )
+            });
+        let (Some((_, interactable)), Some(position)) = (target, position) else {
+            visibility.is_visible = false;
+            return;
+        };
+
+        let label = format!("E: {}", interactable.prompt);
+        // Centred assuming a monospace font
+        let width = label.chars().count() as f32 * PROMPT_FONT_SIZE * 0.6;
+        style.position = UiRect {
+            left: Val::Px(position.x - width / 2.0),
+            bottom: Val::Px(position.y),
+            ..default()
+        };
+        visibility.is_visible = true;
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::{FieldInstance, FieldValue};
+
+// Small lookups for custom fields on LDtk entities and levels.
+// Missing or null fields return None so callers can fall back to a default.
+
+fn field<'a>(fields: &'a [FieldInstance], identifier: &str) -> Option<&'a FieldValue> {
+    fields
+        .iter()
+        .find(|field| field.identifier == identifier)
+        .map(|field| &field.value)
+}
+
+pub fn float_field(fields: &[FieldInstance], identifier: &str) -> Option<f32> {
+    match field(fields, identifier) {
+        Some(FieldValue::Float(value)) => *value,
+        Some(FieldValue::Int(value)) => value.map(|value| value as f32),
+        _ => None,
+    }
+}
+
+pub fn color_field(fields: &[FieldInstance], identifier: &str) -> Option<Color> {
+    match field(fields, identifier) {
+        Some(FieldValue::Color(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn bool_field(fields: &[FieldInstance], identifier: &str) -> Option<bool> {
+    match field(fields, identifier) {
+        Some(FieldValue::Bool(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn string_field(fields: &[FieldInstance], identifier: &str) -> Option<String> {
+    match field(fields, identifier) {
+        Some(FieldValue::String(value)) => value.clone(),
+        _ => None,
+    }
+}
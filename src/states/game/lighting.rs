@@ -0,0 +1,430 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState, Extent3d,
+    RenderPipelineDescriptor, ShaderRef, ShaderType, SpecializedMeshPipelineError,
+    TextureDimension, TextureFormat,
+};
+use bevy::sprite::{Material2d, Material2dKey, MaterialMesh2dBundle};
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use bevy_ecs_ldtk::{EntityInstance, GridCoords, LdtkEntity, LdtkLevel, LevelSelection};
+
+use crate::states::game::components::{CameraTag, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::states::game::ldtk_fields::{color_field, float_field};
+use crate::states::game::terrain::TerrainType;
+use crate::states::game::wall_chunks::TerrainCells;
+
+pub const MAX_LIGHTS: usize = 32;
+
+/// A light placed in LDtk, either a `PointLight` or a `ConeLight`
+#[derive(Component, Clone, Debug)]
+pub struct LightSource {
+    pub color: Color,
+    /// Distance in pixels where the light fades out completely
+    pub radius: f32,
+    pub intensity: f32,
+    pub cone: Option<LightCone>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LightCone {
+    /// Radians, 0 points right and angles go counter clockwise
+    pub direction: f32,
+    /// Full width of the cone in radians
+    pub angle: f32,
+}
+
+impl Default for LightSource {
+    fn default() -> Self {
+        LightSource {
+            color: Color::rgb(1.0, 0.88, 0.66),
+            radius: 96.0,
+            intensity: 1.0,
+            cone: None,
+        }
+    }
+}
+
+impl From<EntityInstance> for LightSource {
+    fn from(entity_instance: EntityInstance) -> LightSource {
+        let default = LightSource::default();
+        let fields = &entity_instance.field_instances;
+        let cone = match entity_instance.identifier.as_ref() {
+            "ConeLight" => Some(LightCone {
+                direction: float_field(fields, "Direction")
+                    .unwrap_or(270.0)
+                    .to_radians(),
+                angle: float_field(fields, "Angle").unwrap_or(60.0).to_radians(),
+            }),
+            _ => None,
+        };
+        LightSource {
+            color: color_field(fields, "Color").unwrap_or(default.color),
+            radius: float_field(fields, "Radius").unwrap_or(default.radius),
+            intensity: float_field(fields, "Intensity").unwrap_or(default.intensity),
+            cone,
+        }
+    }
+}
+
+impl LightSource {
+    /// How much this light contributes at `point`, ignoring walls
+    pub fn contribution(&self, position: Vec2, point: Vec2) -> f32 {
+        let offset = point - position;
+        let distance = offset.length();
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let falloff = 1.0 - distance / self.radius;
+        let mut amount = falloff * falloff * self.intensity;
+        if let Some(cone) = self.cone {
+            if distance > 0.0 {
+                let facing = Vec2::new(cone.direction.cos(), cone.direction.sin());
+                let cos = offset.dot(facing) / distance;
+                let edge = (cone.angle / 2.0).cos();
+                amount *= smoothstep(edge, edge + 0.05, cos);
+            }
+        }
+        amount
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct LightBundle {
+    #[from_entity_instance]
+    pub light: LightSource,
+}
+
+/// How lit an entity is, between 0 (pitch black) and 1.
+/// Add it to anything that stealth systems need to know about.
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Illumination(pub f32);
+
+/// Wall occupancy and ambient light of the current level, mirrored on the gpu by `LightingMaterial`
+#[derive(Resource, Default)]
+pub struct LightMap {
+    pub ambient: f32,
+    pub origin: Vec2,
+    pub grid_size: f32,
+    pub width: i32,
+    pub height: i32,
+    pub walls: HashSet<GridCoords>,
+}
+
+impl LightMap {
+    pub fn is_wall(&self, cell: GridCoords) -> bool {
+        self.walls.contains(&cell)
+    }
+
+    pub fn cell_at(&self, point: Vec2) -> GridCoords {
+        let cell = ((point - self.origin) / self.grid_size).floor();
+        GridCoords {
+            x: cell.x as i32,
+            y: cell.y as i32,
+        }
+    }
+
+    /// Marches from `from` to `to` and checks whether a wall is in between.
+    /// The cell `to` is in doesn't count so walls can still be lit on their faces.
+    pub fn blocked(&self, from: Vec2, to: Vec2) -> bool {
+        if self.grid_size <= 0.0 {
+            return false;
+        }
+        let target_cell = self.cell_at(to);
+        let steps = ((to - from).length() / (self.grid_size / 4.0))
+            .ceil()
+            .min(256.0) as i32;
+        for step in 0..steps {
+            let cell = self.cell_at(from.lerp(to, step as f32 / steps as f32));
+            if cell == target_cell {
+                return false;
+            }
+            if self.is_wall(cell) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The walls as a texture for the lighting shader, one texel per cell
+    pub fn occluders(&self) -> Image {
+        occluder_image(
+            self.width.max(1) as u32,
+            self.height.max(1) as u32,
+            &self.walls,
+        )
+    }
+
+    /// Total light at `point`, the brightest channel of what the lighting shader computes for that pixel
+    pub fn light_at<'a>(
+        &self,
+        point: Vec2,
+        lights: impl Iterator<Item = (Vec2, &'a LightSource)>,
+    ) -> f32 {
+        let mut light = self.ambient;
+        for (position, source) in lights {
+            let contribution = source.contribution(position, point);
+            if contribution > 0.0 && !self.blocked(position, point) {
+                let color = source.color.as_rgba_f32();
+                light += contribution * color[0].max(color[1]).max(color[2]);
+            }
+        }
+        light.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(ShaderType, Clone, Copy, Default)]
+pub struct GpuLight {
+    pub position: Vec2,
+    pub radius: f32,
+    pub intensity: f32,
+    pub color: Color,
+    /// Zero for point lights
+    pub direction: Vec2,
+    /// Cosine of half the cone angle, -1 for point lights
+    pub cone_cos: f32,
+}
+
+#[derive(ShaderType, Clone, Default)]
+pub struct LightingSettings {
+    pub ambient: Color,
+    pub level_origin: Vec2,
+    pub level_cells: Vec2,
+    pub grid_size: f32,
+    pub light_count: u32,
+    pub lights: [GpuLight; MAX_LIGHTS],
+}
+
+/// Full screen overlay that multiplies the scene with the light reaching each pixel
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "4c1a9a7e-4b7e-4d38-9d2b-7f0f8f0c2a11"]
+pub struct LightingMaterial {
+    #[uniform(0)]
+    pub settings: LightingSettings,
+    /// One texel per cell of the current level, red is set for walls
+    #[texture(1)]
+    pub occluders: Handle<Image>,
+}
+
+impl Material2d for LightingMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/lighting.wgsl".into()
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            for target in fragment.targets.iter_mut().flatten() {
+                target.blend = Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: BlendFactor::Dst,
+                        dst_factor: BlendFactor::Zero,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent::OVER,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component)]
+pub struct LightingOverlay;
+
+fn occluder_image(width: u32, height: u32, walls: &HashSet<GridCoords>) -> Image {
+    let mut data = vec![0; (width * height * 4) as usize];
+    for wall in walls {
+        if wall.x >= 0 && wall.y >= 0 && (wall.x as u32) < width && (wall.y as u32) < height {
+            data[((wall.y as u32 * width + wall.x as u32) * 4) as usize] = 255;
+        }
+    }
+    Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    )
+}
+
+/// Spawns the lighting overlay as a child of the game camera so it always covers the view
+pub fn spawn_lighting_overlay(
+    camera: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    lighting_materials: &mut Assets<LightingMaterial>,
+    images: &mut Assets<Image>,
+    light_map: &LightMap,
+) {
+    let quad_handle = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(
+        SCREEN_WIDTH as f32,
+        SCREEN_HEIGHT as f32,
+    ))));
+    let material_handle = lighting_materials.add(LightingMaterial {
+        settings: LightingSettings::default(),
+        occluders: images.add(light_map.occluders()),
+    });
+    camera.spawn((
+        MaterialMesh2dBundle {
+            mesh: quad_handle.into(),
+            material: material_handle,
+            // The camera sits at the bottom left of the view, just behind the far plane
+            transform: Transform::from_xyz(
+                SCREEN_WIDTH as f32 / 2.,
+                SCREEN_HEIGHT as f32 / 2.,
+                -1.0,
+            ),
+            ..default()
+        },
+        LightingOverlay,
+    ));
+}
+
+/// Rebuilds the light map whenever `rebuild_wall_chunks` sees terrain tiles spawn, despawn
+/// or change, or the player moves to another level
+pub fn update_light_map(
+    terrain_query: Query<(&GridCoords, &TerrainType, &Parent)>,
+    terrain_cells: Res<TerrainCells>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>, &Transform)>,
+    levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    mut light_map: ResMut<LightMap>,
+) {
+    if !terrain_cells.is_changed() && !level_selection.is_changed() {
+        return;
+    }
+    for (level_entity, level_handle, level_transform) in &level_query {
+        if let Some(ldtk_level) = levels.get(level_handle) {
+            if !level_selection.is_match(&0, &ldtk_level.level) {
+                continue;
+            }
+
+            // The walls come from the IntGrid layer, so the light map takes its size
+            let mut terrain_layer = None;
+            let mut walls = HashSet::new();
+            for (grid_coords, terrain, parent) in &terrain_query {
+                let Ok((layer, level)) = layer_query.get(parent.get()) else {
+                    continue;
+                };
+                if level.get() != level_entity {
+                    continue;
+                }
+                terrain_layer = Some(layer);
+                if terrain.rules().opaque {
+                    walls.insert(*grid_coords);
+                }
+            }
+            // A level without any terrain is lit as if it was all floor
+            let Some(layer) = terrain_layer.or_else(|| {
+                layer_query
+                    .iter()
+                    .find(|(_, level)| level.get() == level_entity)
+                    .map(|(layer, _)| layer)
+            }) else {
+                continue;
+            };
+
+            let darkness =
+                float_field(&ldtk_level.level.field_instances, "Darkness").unwrap_or(0.0);
+            *light_map = LightMap {
+                ambient: 1.0 - darkness.clamp(0.0, 1.0),
+                origin: level_transform.translation.truncate(),
+                grid_size: layer.grid_size as f32,
+                width: layer.c_wid,
+                height: layer.c_hei,
+                walls,
+            };
+        }
+    }
+}
+
+/// Sends the lights closest to the camera to the lighting shader, and the walls when they change
+pub fn update_lighting(
+    light_query: Query<(&GlobalTransform, &LightSource)>,
+    camera_query: Query<&GlobalTransform, With<CameraTag>>,
+    overlay_query: Query<&Handle<LightingMaterial>, With<LightingOverlay>>,
+    mut lighting_materials: ResMut<Assets<LightingMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    light_map: Res<LightMap>,
+) {
+    if light_map.is_changed() {
+        let occluders = images.add(light_map.occluders());
+        for material_handle in &overlay_query {
+            if let Some(material) = lighting_materials.get_mut(material_handle) {
+                material.occluders = occluders.clone();
+            }
+        }
+    }
+
+    let camera_position = match camera_query.get_single() {
+        Ok(camera_transform) => camera_transform.translation().truncate(),
+        Err(_) => return,
+    };
+    let view_center = camera_position + Vec2::new(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32) / 2.;
+
+    let mut lights: Vec<(Vec2, &LightSource)> = light_query
+        .iter()
+        .map(|(transform, light)| (transform.translation().truncate(), light))
+        .collect();
+    lights.sort_by(|(a, _), (b, _)| {
+        a.distance_squared(view_center)
+            .total_cmp(&b.distance_squared(view_center))
+    });
+
+    let mut settings = LightingSettings {
+        ambient: Color::rgb(light_map.ambient, light_map.ambient, light_map.ambient),
+        level_origin: light_map.origin,
+        level_cells: Vec2::new(light_map.width as f32, light_map.height as f32),
+        grid_size: light_map.grid_size,
+        light_count: lights.len().min(MAX_LIGHTS) as u32,
+        ..Default::default()
+    };
+    for (gpu_light, (position, light)) in settings.lights.iter_mut().zip(lights) {
+        *gpu_light = GpuLight {
+            position,
+            radius: light.radius,
+            intensity: light.intensity,
+            color: light.color,
+            direction: light.cone.map_or(Vec2::ZERO, |cone| {
+                Vec2::new(cone.direction.cos(), cone.direction.sin())
+            }),
+            cone_cos: light.cone.map_or(-1.0, |cone| (cone.angle / 2.0).cos()),
+        };
+    }
+
+    for material_handle in &overlay_query {
+        if let Some(material) = lighting_materials.get_mut(material_handle) {
+            material.settings = settings.clone();
+        }
+    }
+}
+
+pub fn update_illumination(
+    mut lit_query: Query<(&GlobalTransform, &mut Illumination)>,
+    light_query: Query<(&GlobalTransform, &LightSource)>,
+    light_map: Res<LightMap>,
+) {
+    for (transform, mut illumination) in &mut lit_query {
+        illumination.0 = light_map.light_at(
+            transform.translation().truncate(),
+            light_query
+                .iter()
+                .map(|(transform, light)| (transform.translation().truncate(), light)),
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::Level;
+use bevy_ecs_ldtk::{LdtkAsset, LdtkLevel, LevelSelection};
+
+use crate::components::Game;
+use crate::save::SaveData;
+
+/// Fraction of the window the map may take up
+const MAP_FILL: f32 = 0.8;
+const MARKER_SIZE: f32 = 6.0;
+
+const BACKGROUND_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.85);
+const ROOM_COLOR: Color = Color::rgb(0.3, 0.3, 0.35);
+const CURRENT_ROOM_COLOR: Color = Color::rgb(0.5, 0.6, 0.9);
+const EXIT_COLOR: Color = Color::rgb(0.3, 1.0, 0.4);
+const COLLECTED_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+const NOT_COLLECTED_COLOR: Color = Color::rgb(0.5, 0.45, 0.3);
+
+/// M shows or hides the map of the rooms explored so far
+#[derive(Resource, Default)]
+pub struct MapScreen {
+    pub open: bool,
+}
+
+#[derive(Component)]
+pub struct MapScreenPanel;
+
+/// Adds the current room to the explored ones in the save data
+pub fn explore_rooms(
+    level_query: Query<&Handle<LdtkLevel>>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    mut save: ResMut<SaveData>,
+) {
+    let current = level_query
+        .iter()
+        .filter_map(|level_handle| ldtk_levels.get(level_handle))
+        .find(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level));
+    if let Some(ldtk_level) = current {
+        if !save.explored.contains(&ldtk_level.level.iid) {
+            save.explored.insert(ldtk_level.level.iid.clone());
+        }
+    }
+}
+
+pub fn toggle_map_screen(keys: Res<Input<KeyCode>>, mut map_screen: ResMut<MapScreen>) {
+    if keys.just_pressed(KeyCode::M) {
+        map_screen.open = !map_screen.open;
+    }
+}
+
+/// Where `level` goes on the map, in pixels from its top left corner.
+/// LDtk world coordinates already point down like UI ones.
+fn map_rect(level: &Level, origin: Vec2, scale: f32) -> (Vec2, Vec2) {
+    let position = (Vec2::new(level.world_x as f32, level.world_y as f32) - origin) * scale;
+    let size = Vec2::new(level.px_wid as f32, level.px_hei as f32) * scale;
+    (position, size)
+}
+
+fn spawn_marker(parent: &mut ChildBuilder, position: Vec2, color: Color) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(position.x - MARKER_SIZE / 2.0),
+                top: Val::Px(position.y - MARKER_SIZE / 2.0),
+                ..default()
+            },
+            size: Size::new(Val::Px(MARKER_SIZE), Val::Px(MARKER_SIZE)),
+            ..default()
+        },
+        background_color: color.into(),
+        ..default()
+    });
+}
+
+/// Rebuilds the map whenever it is opened, or something on it changes while it is open
+#[allow(clippy::too_many_arguments)]
+pub fn update_map_screen(
+    mut commands: Commands,
+    map_screen: Res<MapScreen>,
+    panel_query: Query<Entity, With<MapScreenPanel>>,
+    world_query: Query<&Handle<LdtkAsset>>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    level_selection: Res<LevelSelection>,
+    save: Res<SaveData>,
+    windows: Res<Windows>,
+) {
+    if !map_screen.is_changed() && !level_selection.is_changed() && !save.is_changed() {
+        return;
+    }
+    for panel in &panel_query {
+        commands.entity(panel).despawn_recursive();
+    }
+    if !map_screen.open {
+        return;
+    }
+    let (Some(ldtk_asset), Some(window)) = (
+        world_query
+            .iter()
+            .next()
+            .and_then(|world_handle| ldtk_assets.get(world_handle)),
+        windows.get_primary(),
+    ) else {
+        return;
+    };
+    let explored: Vec<&Level> = ldtk_asset
+        .iter_levels()
+        .filter(|level| save.explored.contains(&level.iid))
+        .collect();
+    let Some(first) = explored.first() else {
+        return;
+    };
+
+    // Fit the explored part of the world into the middle of the window
+    let (min, max) = explored.iter().fold(
+        (
+            Vec2::new(first.world_x as f32, first.world_y as f32),
+            Vec2::new(first.world_x as f32, first.world_y as f32),
+        ),
+        |(min, max), level| {
+            let top_left = Vec2::new(level.world_x as f32, level.world_y as f32);
+            let bottom_right = top_left + Vec2::new(level.px_wid as f32, level.px_hei as f32);
+            (min.min(top_left), max.max(bottom_right))
+        },
+    );
+    let window_size = Vec2::new(window.width(), window.height());
+    let available = window_size * MAP_FILL;
+    let scale = (available / (max - min).max(Vec2::ONE)).min_element();
+    let map_size = (max - min) * scale;
+    let map_corner = (window_size - map_size) / 2.0;
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(0.0),
+                        top: Val::Px(0.0),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    ..default()
+                },
+                background_color: BACKGROUND_COLOR.into(),
+                ..default()
+            },
+            MapScreenPanel,
+            Game,
+        ))
+        .with_children(|panel| {
+            for level in &explored {
+                let (position, size) = map_rect(level, min, scale);
+                let position = map_corner + position;
+                let color = if level_selection.is_match(&0, level) {
+                    CURRENT_ROOM_COLOR
+                } else {
+                    ROOM_COLOR
+                };
+                panel.spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px(position.x),
+                            top: Val::Px(position.y),
+                            ..default()
+                        },
+                        // A gap between neighbouring rooms
+                        size: Size::new(Val::Px(size.x - 2.0), Val::Px(size.y - 2.0)),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    ..default()
+                });
+
+                let entities = level
+                    .layer_instances
+                    .iter()
+                    .flatten()
+                    .flat_map(|layer| &layer.entity_instances);
+                for entity in entities {
+                    let center = position
+                        + (entity.px.as_vec2()
+                            + Vec2::new(entity.width as f32, entity.height as f32) / 2.0
+                            - entity.pivot * Vec2::new(entity.width as f32, entity.height as f32))
+                            * scale;
+                    let color = match entity.identifier.as_str() {
+                        "Exit" => EXIT_COLOR,
+                        "Collectible" if save.collected.contains(&entity.iid) => COLLECTED_COLOR,
+                        "Collectible" => NOT_COLLECTED_COLOR,
+                        _ => continue,
+                    };
+                    spawn_marker(panel, center, color);
+                }
+            }
+        });
+}
@@ -1,2 +1,21 @@
+pub mod animation;
+pub mod aseprite;
+pub mod camera;
+pub mod colliders;
 pub mod components;
+pub mod console;
+pub mod crowd;
+pub mod debug;
+pub mod doors;
+pub mod drawing;
+pub mod interaction;
+pub mod ldtk_fields;
+pub mod lighting;
+pub mod map_screen;
+pub mod objectives;
+pub mod post_processing;
+pub mod replay;
 pub mod systems;
+pub mod terrain;
+pub mod transitions;
+pub mod wall_chunks;
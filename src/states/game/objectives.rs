@@ -0,0 +1,410 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{EntityInstance, LdtkEntity, LdtkLevel, LevelSelection};
+use bevy_rapier2d::prelude::RapierConfiguration;
+use iyes_loopless::state::NextState;
+
+use crate::components::{Game, GameState, InGame, Player};
+use crate::save::SaveData;
+use crate::states::game::camera::ScreenShake;
+use crate::states::game::components::{Enemy, ItemTag, PathInit};
+use crate::states::game::console::Cheats;
+use crate::states::game::interaction::{InteractEvent, Interactable, InteractionKind};
+use crate::states::game::ldtk_fields::string_field;
+use crate::states::game::lighting::{Illumination, LightMap};
+use crate::states::game::post_processing::PostProcessingStack;
+
+/// How far enemies can see a fully lit player, darkness shrinks it
+const DETECTION_RANGE: f32 = 96.0;
+
+/// How dark the edges of the screen get while an enemy can see the player
+const DETECTED_VIGNETTE: f32 = 0.6;
+const DETECTED_COLOR: Color = Color::rgb(0.5, 0.0, 0.0);
+
+/// How far enemies can see the player at `illumination`
+pub fn detection_range(illumination: f32) -> f32 {
+    DETECTION_RANGE * (0.3 + 0.7 * illumination.clamp(0.0, 1.0))
+}
+
+/// Whether an enemy at `enemy_position` can see the player, in range and not behind a wall
+pub fn sees_player(
+    enemy_position: Vec2,
+    player_position: Vec2,
+    illumination: f32,
+    light_map: &LightMap,
+) -> bool {
+    enemy_position.distance(player_position) <= detection_range(illumination)
+        && !light_map.blocked(enemy_position, player_position)
+}
+
+/// Goals of a level, placed as LDtk entities.
+/// The level is complete once the player reaches an exit with every item collected.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Objective {
+    #[default]
+    ReachExit,
+    Collect(String),
+    AvoidDetection,
+}
+
+impl Objective {
+    pub fn description(&self) -> String {
+        match self {
+            Objective::ReachExit => "Reach the exit".to_string(),
+            Objective::Collect(name) => format!("Collect the {}", name),
+            Objective::AvoidDetection => "Stay undetected".to_string(),
+        }
+    }
+
+    /// Position on the results card
+    fn order(&self) -> u8 {
+        match self {
+            Objective::ReachExit => 0,
+            Objective::Collect(_) => 1,
+            Objective::AvoidDetection => 2,
+        }
+    }
+}
+
+impl From<EntityInstance> for Objective {
+    fn from(entity_instance: EntityInstance) -> Objective {
+        match entity_instance.identifier.as_ref() {
+            "Collectible" => Objective::Collect(collectible_name(&entity_instance)),
+            "AvoidDetection" => Objective::AvoidDetection,
+            _ => Objective::ReachExit,
+        }
+    }
+}
+
+pub fn collectible_name(entity_instance: &EntityInstance) -> String {
+    string_field(&entity_instance.field_instances, "Name").unwrap_or_else(|| "item".to_string())
+}
+
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectiveState {
+    #[default]
+    Pending,
+    Complete,
+    Failed,
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct ExitBundle {
+    #[sprite_bundle("exit.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub objective: Objective,
+    pub state: ObjectiveState,
+    game: Game,
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct CollectibleBundle {
+    #[sprite_bundle("collectible.png")]
+    #[bundle]
+    pub sprite_bundle: SpriteBundle,
+    #[from_entity_instance]
+    pub objective: Objective,
+    pub state: ObjectiveState,
+    #[from_entity_instance]
+    pub interaction: Interactable,
+    #[from_entity_instance]
+    pub entity_instance: EntityInstance,
+    game: Game,
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct AvoidDetectionBundle {
+    #[from_entity_instance]
+    pub objective: Objective,
+    pub state: ObjectiveState,
+    game: Game,
+}
+
+#[derive(Resource, Default)]
+pub struct LevelProgress {
+    pub started: f32,
+    /// Set once the player reaches the exit, the results card is shown until they continue
+    pub finished: Option<f32>,
+    /// Identifier of the level to load next, from the NextLevel field. None after the last level.
+    pub next_level: Option<String>,
+}
+
+#[derive(Component)]
+pub struct ResultsCard;
+
+/// The level `LevelSelection` picks. Neighbouring levels are loaded too,
+/// only the objectives that are children of this one count.
+fn current_level(
+    level_query: &Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: &Assets<LdtkLevel>,
+    level_selection: &LevelSelection,
+) -> Option<Entity> {
+    level_query.iter().find_map(|(level_entity, level_handle)| {
+        ldtk_levels
+            .get(level_handle)
+            .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+            .map(|_| level_entity)
+    })
+}
+
+pub fn start_level(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    commands.insert_resource(LevelProgress {
+        started: time.elapsed_seconds(),
+        ..default()
+    });
+    commands.insert_resource(PathInit(false));
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn collect_objectives(
+    mut commands: Commands,
+    mut interact_events: EventReader<InteractEvent>,
+    mut objective_query: Query<(
+        &Objective,
+        &mut ObjectiveState,
+        &mut Visibility,
+        &EntityInstance,
+        &Parent,
+    )>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    mut save: ResMut<SaveData>,
+) {
+    let level = current_level(&level_query, &ldtk_levels, &level_selection);
+    for event in interact_events.iter() {
+        if event.kind != InteractionKind::PickUp {
+            continue;
+        }
+        if let Ok((Objective::Collect(_), mut state, mut visibility, entity_instance, parent)) =
+            objective_query.get_mut(event.target)
+        {
+            if Some(parent.get()) != level {
+                continue;
+            }
+            save.collected.insert(entity_instance.iid.clone());
+            *state = ObjectiveState::Complete;
+            visibility.is_visible = false;
+            commands.entity(event.target).remove::<Interactable>();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn detect_player(
+    enemy_query: Query<&GlobalTransform, With<Enemy>>,
+    player_query: Query<(&GlobalTransform, &Illumination), (With<Player>, Without<ItemTag>)>,
+    mut objective_query: Query<(&Objective, &mut ObjectiveState, &Parent)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    light_map: Res<LightMap>,
+    progress: Res<LevelProgress>,
+    cheats: Res<Cheats>,
+    mut shake: ResMut<ScreenShake>,
+    mut stack: ResMut<PostProcessingStack>,
+    mut was_detected: Local<bool>,
+) {
+    let detected = progress.finished.is_none()
+        && !cheats.god
+        && player_query
+            .get_single()
+            .map_or(false, |(player_transform, illumination)| {
+                let player_position = player_transform.translation().truncate();
+                enemy_query.iter().any(|enemy_transform| {
+                    sees_player(
+                        enemy_transform.translation().truncate(),
+                        player_position,
+                        illumination.0,
+                        &light_map,
+                    )
+                })
+            });
+
+    // The edges of the screen close in while the player is seen, with a flash when it starts
+    let vignette = if detected { DETECTED_VIGNETTE } else { 0.0 };
+    if stack.vignette.intensity != vignette {
+        stack.vignette.intensity = vignette;
+        stack.vignette.color = DETECTED_COLOR;
+    }
+    if detected && !*was_detected {
+        stack.flash(DETECTED_COLOR, 0.5);
+    }
+    *was_detected = detected;
+
+    if detected {
+        let level = current_level(&level_query, &ldtk_levels, &level_selection);
+        for (objective, mut state, parent) in &mut objective_query {
+            if *objective == Objective::AvoidDetection
+                && *state == ObjectiveState::Pending
+                && Some(parent.get()) == level
+            {
+                *state = ObjectiveState::Failed;
+                shake.add_trauma(0.8);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn reach_exit(
+    mut commands: Commands,
+    player_query: Query<&GlobalTransform, (With<Player>, Without<ItemTag>)>,
+    mut objective_query: Query<(&Objective, &mut ObjectiveState, &GlobalTransform, &Parent)>,
+    mut progress: ResMut<LevelProgress>,
+    mut save: ResMut<SaveData>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+) {
+    if progress.finished.is_some() {
+        return;
+    }
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let Some(level) = current_level(&level_query, &ldtk_levels, &level_selection) else {
+        return;
+    };
+    let player_position = player_transform.translation().truncate();
+    let in_level = |parent: &Parent| parent.get() == level;
+    let collected = objective_query.iter().all(|(objective, state, _, parent)| {
+        !in_level(parent)
+            || !matches!(objective, Objective::Collect(_))
+            || *state == ObjectiveState::Complete
+    });
+    let at_exit = objective_query
+        .iter()
+        .any(|(objective, _, transform, parent)| {
+            // Exits are scaled to their size in LDtk
+            let (scale, _, translation) = transform.to_scale_rotation_translation();
+            in_level(parent)
+                && *objective == Objective::ReachExit
+                && ((player_position - translation.truncate()).abs() - scale.truncate() * 8.0)
+                    .max_element()
+                    <= 0.0
+        });
+    if !(collected && at_exit) {
+        return;
+    }
+
+    let mut lines = Vec::new();
+    for (objective, mut state, _, parent) in &mut objective_query {
+        if !in_level(parent) {
+            continue;
+        }
+        if *state == ObjectiveState::Pending {
+            *state = ObjectiveState::Complete;
+        }
+        let mark = if *state == ObjectiveState::Complete {
+            "x"
+        } else {
+            " "
+        };
+        lines.push((
+            objective.order(),
+            format!("[{}] {}", mark, objective.description()),
+        ));
+    }
+    // Every exit is listed once
+    lines.sort();
+    lines.dedup();
+    let mut lines: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+
+    let finished = time.elapsed_seconds();
+    progress.finished = Some(finished);
+    if let Some(ldtk_level) = level_query
+        .get(level)
+        .ok()
+        .and_then(|(_, level_handle)| ldtk_levels.get(level_handle))
+    {
+        save.completed.insert(ldtk_level.level.iid.clone());
+        progress.next_level = string_field(&ldtk_level.level.field_instances, "NextLevel");
+    }
+    rapier_configuration.physics_pipeline_active = false;
+
+    lines.push(format!("Time {:.1}s", finished - progress.started));
+    lines.push(String::new());
+    lines.push(if progress.next_level.is_some() {
+        "Enter: next level".to_string()
+    } else {
+        "Enter: back to menu".to_string()
+    });
+    spawn_results_card(&mut commands, &asset_server, lines);
+}
+
+fn spawn_results_card(commands: &mut Commands, asset_server: &AssetServer, lines: Vec<String>) {
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+                ..default()
+            },
+            ResultsCard,
+            Game,
+        ))
+        .with_children(|card| {
+            card.spawn(
+                TextBundle::from_sections([
+                    TextSection::new(
+                        "Level complete\n\n",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 32.0,
+                            color: Color::WHITE,
+                        },
+                    ),
+                    TextSection::new(
+                        lines.join("\n"),
+                        TextStyle {
+                            font,
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                    ),
+                ])
+                .with_text_alignment(TextAlignment::CENTER),
+            );
+        });
+}
+
+/// Loads the next level once the player dismisses the results card, by restarting the game state
+pub fn continue_from_results(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    progress: Res<LevelProgress>,
+    mut level_selection: ResMut<LevelSelection>,
+    mut ingame: ResMut<InGame>,
+) {
+    if progress.finished.is_none() || !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+    ingame.0 = false;
+    match &progress.next_level {
+        Some(next_level) => {
+            *level_selection = LevelSelection::Identifier(next_level.clone());
+            commands.insert_resource(NextState(GameState::Game));
+        }
+        None => {
+            *level_selection = LevelSelection::Index(0);
+            commands.insert_resource(NextState(GameState::Menu));
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+
+use crate::components::{PostProcessingMaterial, PostProcessingSettings};
+use crate::states::game::components::{RenderImage, SCREEN_HEIGHT};
+
+/// The passes understood by `post_processing.wgsl`, the discriminant is the id the shader switches on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostEffect {
+    ChromaticAberration = 0,
+    Vignette = 1,
+    Scanlines = 2,
+    ColorGrade = 3,
+    Flash = 4,
+}
+
+pub const MAX_POST_PASSES: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct Vignette {
+    pub intensity: f32,
+    /// Distance from the centre of the screen (in uv) where the vignette is fully applied
+    pub radius: f32,
+    pub softness: f32,
+    pub color: Color,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scanlines {
+    pub intensity: f32,
+    /// Number of dark bands from the top to the bottom of the screen
+    pub count: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ColorGrade {
+    pub strength: f32,
+    /// A 256x16 strip of 16 lut slices, falls back to an identity lut when None
+    pub lut: Option<Handle<Image>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Flash {
+    pub intensity: f32,
+    pub color: Color,
+    /// How much intensity is lost per second
+    pub decay: f32,
+}
+
+/// Ordered chain of effects applied to the low res render target.
+/// Gameplay can change the order or any of the parameters at runtime,
+/// effects that aren't in `passes` are skipped.
+#[derive(Resource, Clone, Debug)]
+pub struct PostProcessingStack {
+    pub passes: Vec<PostEffect>,
+    /// Offset of the red and blue channels in uv space
+    pub chromatic_aberration: f32,
+    pub vignette: Vignette,
+    pub scanlines: Scanlines,
+    pub color_grade: ColorGrade,
+    pub flash: Flash,
+}
+
+impl Default for PostProcessingStack {
+    fn default() -> Self {
+        PostProcessingStack {
+            passes: vec![
+                PostEffect::ColorGrade,
+                PostEffect::Vignette,
+                PostEffect::Scanlines,
+                PostEffect::ChromaticAberration,
+                PostEffect::Flash,
+            ],
+            chromatic_aberration: 0.0,
+            vignette: Vignette {
+                intensity: 0.0,
+                radius: 0.75,
+                softness: 0.45,
+                color: Color::BLACK,
+            },
+            scanlines: Scanlines {
+                intensity: 0.0,
+                count: SCREEN_HEIGHT as f32,
+            },
+            color_grade: ColorGrade {
+                strength: 0.0,
+                lut: None,
+            },
+            flash: Flash {
+                intensity: 0.0,
+                color: Color::WHITE,
+                decay: 4.0,
+            },
+        }
+    }
+}
+
+impl PostProcessingStack {
+    /// Fills the screen with a colour that fades out over time
+    pub fn flash(&mut self, color: Color, intensity: f32) {
+        self.flash.color = color;
+        self.flash.intensity = intensity;
+    }
+
+    pub fn settings(&self) -> PostProcessingSettings {
+        let mut passes = [UVec4::ZERO; MAX_POST_PASSES / 4];
+        for (i, effect) in self.passes.iter().take(MAX_POST_PASSES).enumerate() {
+            passes[i / 4][i % 4] = *effect as u32;
+        }
+        PostProcessingSettings {
+            passes,
+            pass_count: self.passes.len().min(MAX_POST_PASSES) as u32,
+            chromatic_aberration: self.chromatic_aberration,
+            vignette_intensity: self.vignette.intensity,
+            vignette_radius: self.vignette.radius,
+            vignette_softness: self.vignette.softness,
+            vignette_color: self.vignette.color,
+            scanline_intensity: self.scanlines.intensity,
+            scanline_count: self.scanlines.count,
+            grade_strength: self.color_grade.strength,
+            flash_intensity: self.flash.intensity,
+            flash_color: self.flash.color,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct IdentityLut(pub Handle<Image>);
+
+/// Builds a lut that maps every colour to itself.
+/// Custom luts use the same layout: 16 slices of 16x16 side by side, blue picks the slice.
+pub fn identity_lut() -> Image {
+    let size = 16;
+    let mut data = Vec::with_capacity(size * size * size * 4);
+    for g in 0..size {
+        for b in 0..size {
+            for r in 0..size {
+                data.extend_from_slice(&[
+                    (r * 255 / (size - 1)) as u8,
+                    (g * 255 / (size - 1)) as u8,
+                    (b * 255 / (size - 1)) as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+    let mut image = Image::new(
+        Extent3d {
+            width: (size * size) as u32,
+            height: size as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler_descriptor = ImageSampler::linear();
+    image
+}
+
+pub fn update_post_processing(
+    stack: Res<PostProcessingStack>,
+    identity_lut: Res<IdentityLut>,
+    material_query: Query<&Handle<PostProcessingMaterial>, With<RenderImage>>,
+    mut post_processing_materials: ResMut<Assets<PostProcessingMaterial>>,
+) {
+    if !stack.is_changed() {
+        return;
+    }
+    for material_handle in &material_query {
+        if let Some(material) = post_processing_materials.get_mut(material_handle) {
+            material.settings = stack.settings();
+            material.lut = stack
+                .color_grade
+                .lut
+                .clone()
+                .unwrap_or_else(|| identity_lut.0.clone());
+        }
+    }
+}
+
+pub fn fade_flash(mut stack: ResMut<PostProcessingStack>, time: Res<Time>) {
+    if stack.flash.intensity > 0.0 {
+        let decay = stack.flash.decay;
+        stack.flash.intensity = (stack.flash.intensity - decay * time.delta_seconds()).max(0.0);
+    }
+}
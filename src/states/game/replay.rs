@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::LevelSelection;
+use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode};
+use serde::{Deserialize, Serialize};
+
+use crate::components::{MapPath, Player};
+use crate::states::game::components::{ItemTag, WorldMouseCoords};
+
+/// Where the game records to with `--record`, and what the menu plays back
+pub const LAST_REPLAY_PATH: &str = "replays/last.ron";
+
+pub const FIXED_TIMESTEP_DT: f32 = 1.0 / 60.0;
+
+/// Physics advances by exactly this much every frame while recording or playing back
+pub const FIXED_TIMESTEP: TimestepMode = TimestepMode::Fixed {
+    dt: FIXED_TIMESTEP_DT,
+    substeps: 1,
+};
+
+/// Seeded source of randomness. Anything random has to come from here
+/// so replays can reproduce it, see `Replay::seed`.
+// fastrand::Rng isn't Sync, the mutexes are only there to make it a resource
+#[derive(Resource)]
+pub struct GameRng {
+    gameplay: Mutex<fastrand::Rng>,
+    view: Mutex<fastrand::Rng>,
+}
+
+impl GameRng {
+    pub fn with_seed(seed: u64) -> Self {
+        GameRng {
+            gameplay: Mutex::new(fastrand::Rng::with_seed(seed)),
+            // Its own stream, so headless runs without a camera draw the same gameplay numbers
+            view: Mutex::new(fastrand::Rng::with_seed(seed.rotate_left(32))),
+        }
+    }
+
+    pub fn rng(&mut self) -> &fastrand::Rng {
+        self.gameplay
+            .get_mut()
+            .expect("rng mutex should never be poisoned")
+    }
+
+    /// For what only changes how things look, like camera shake
+    pub fn view_rng(&mut self) -> &fastrand::Rng {
+        self.view
+            .get_mut()
+            .expect("rng mutex should never be poisoned")
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::with_seed(fastrand::u64(..))
+    }
+}
+
+/// The input of a single frame, as the gameplay systems saw it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReplayFrame {
+    pub keys: Vec<KeyCode>,
+    pub mouse_buttons: Vec<MouseButton>,
+    /// `WorldMouseCoords`
+    pub aim: [f32; 2],
+}
+
+/// Mirror of `LevelSelection`, which can't be serialized
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ReplayLevel {
+    Identifier(String),
+    Index(usize),
+    Iid(String),
+    Uid(i32),
+}
+
+impl From<&LevelSelection> for ReplayLevel {
+    fn from(level_selection: &LevelSelection) -> Self {
+        match level_selection {
+            LevelSelection::Identifier(identifier) => ReplayLevel::Identifier(identifier.clone()),
+            LevelSelection::Index(index) => ReplayLevel::Index(*index),
+            LevelSelection::Iid(iid) => ReplayLevel::Iid(iid.clone()),
+            LevelSelection::Uid(uid) => ReplayLevel::Uid(*uid),
+        }
+    }
+}
+
+impl From<&ReplayLevel> for LevelSelection {
+    fn from(level: &ReplayLevel) -> Self {
+        match level {
+            ReplayLevel::Identifier(identifier) => LevelSelection::Identifier(identifier.clone()),
+            ReplayLevel::Index(index) => LevelSelection::Index(*index),
+            ReplayLevel::Iid(iid) => LevelSelection::Iid(iid.clone()),
+            ReplayLevel::Uid(uid) => LevelSelection::Uid(*uid),
+        }
+    }
+}
+
+/// A recorded run. Frame 0 is the first frame the player exists in,
+/// so the time the map takes to load doesn't matter.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub map: String,
+    pub level: ReplayLevel,
+    /// Of `GameRng`
+    pub seed: u64,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> Result<Replay, Box<dyn Error>> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Records every Game session, see `record_input`
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    /// Where the replay is written when the session ends, None keeps it in memory only
+    pub path: Option<PathBuf>,
+    pub replay: Option<Replay>,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        ReplayRecorder { path, replay: None }
+    }
+}
+
+/// Feeds a replay back instead of the real input, removed once it runs out of frames
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub frame: usize,
+}
+
+/// The map and level a playback swapped out, put back by `start_replay`
+/// once a session starts that isn't a replay
+#[derive(Resource)]
+pub struct BeforeReplay {
+    pub map: MapPath,
+    pub level: LevelSelection,
+    pub replay_map: String,
+    pub replay_level: ReplayLevel,
+}
+
+impl BeforeReplay {
+    pub fn new(map: &MapPath, level: &LevelSelection, replay: &Replay) -> Self {
+        BeforeReplay {
+            map: map.clone(),
+            level: level.clone(),
+            replay_map: replay.map.clone(),
+            replay_level: replay.level.clone(),
+        }
+    }
+}
+
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReplaySystem;
+
+/// Sets up `app` to play `replay` from the start of the Game state
+pub fn add_playback(app: &mut App, replay: Replay) -> &mut App {
+    let before_replay = BeforeReplay::new(
+        &app.world
+            .get_resource::<MapPath>()
+            .cloned()
+            .unwrap_or_default(),
+        &app.world
+            .get_resource::<LevelSelection>()
+            .cloned()
+            .unwrap_or_default(),
+        &replay,
+    );
+    app.insert_resource(before_replay)
+        .insert_resource(MapPath(replay.map.clone()))
+        .insert_resource(LevelSelection::from(&replay.level))
+        .insert_resource(ReplayPlayback { replay, frame: 0 })
+}
+
+/// Seeds the rng and fixes the physics timestep for a recording or a playback.
+/// Any other session undoes what the last playback changed, see `BeforeReplay`.
+#[allow(clippy::too_many_arguments)]
+pub fn start_replay(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
+    before_replay: Option<Res<BeforeReplay>>,
+    mut map_path: ResMut<MapPath>,
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    let seed = if let Some(playback) = playback {
+        playback.replay.seed
+    } else if let Some(mut recorder) = recorder {
+        let seed = fastrand::u64(..);
+        recorder.replay = Some(Replay {
+            map: map_path.0.clone(),
+            level: ReplayLevel::from(level_selection.as_ref()),
+            seed,
+            frames: Vec::new(),
+        });
+        seed
+    } else {
+        if let Some(before_replay) = before_replay {
+            // Unless something else, like the level select, already picked what to play
+            if map_path.0 == before_replay.replay_map
+                && ReplayLevel::from(level_selection.as_ref()) == before_replay.replay_level
+            {
+                *map_path = before_replay.map.clone();
+                *level_selection = before_replay.level.clone();
+            }
+            rapier_configuration.timestep_mode = RapierConfiguration::default().timestep_mode;
+            commands.remove_resource::<BeforeReplay>();
+        }
+        return;
+    };
+    *rng = GameRng::with_seed(seed);
+    rapier_configuration.timestep_mode = FIXED_TIMESTEP;
+}
+
+pub fn record_input(
+    mut recorder: ResMut<ReplayRecorder>,
+    player_query: Query<(), (With<Player>, Without<ItemTag>)>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    world_coords: Res<WorldMouseCoords>,
+) {
+    if player_query.is_empty() {
+        return;
+    }
+    if let Some(replay) = &mut recorder.replay {
+        replay.frames.push(ReplayFrame {
+            keys: keys.get_pressed().copied().collect(),
+            mouse_buttons: mouse_buttons.get_pressed().copied().collect(),
+            aim: world_coords.0.to_array(),
+        });
+    }
+}
+
+/// Replaces this frame's input with the next replay frame
+pub fn play_input(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    player_query: Query<(), (With<Player>, Without<ItemTag>)>,
+    mut keys: ResMut<Input<KeyCode>>,
+    mut mouse_buttons: ResMut<Input<MouseButton>>,
+    mut world_coords: ResMut<WorldMouseCoords>,
+) {
+    if player_query.is_empty() {
+        return;
+    }
+    let frames = &playback.replay.frames;
+    let Some(frame) = frames.get(playback.frame) else {
+        return;
+    };
+    let previous = playback
+        .frame
+        .checked_sub(1)
+        .map(|previous| &frames[previous]);
+
+    *keys = replay_buttons(
+        previous.map_or(&[][..], |previous| &previous.keys),
+        &frame.keys,
+    );
+    *mouse_buttons = replay_buttons(
+        previous.map_or(&[][..], |previous| &previous.mouse_buttons),
+        &frame.mouse_buttons,
+    );
+    world_coords.0 = Vec2::from_array(frame.aim);
+
+    let frame_count = frames.len();
+    playback.frame += 1;
+    if playback.frame == frame_count {
+        info!(frames = frame_count, "Replay finished");
+        commands.remove_resource::<ReplayPlayback>();
+    }
+}
+
+/// Rebuilds an `Input` so `just_pressed` and `just_released` match the change between frames
+fn replay_buttons<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    previous: &[T],
+    current: &[T],
+) -> Input<T> {
+    let mut input = Input::default();
+    for &button in previous {
+        input.press(button);
+    }
+    input.clear();
+    for &button in previous {
+        if !current.contains(&button) {
+            input.release(button);
+        }
+    }
+    for &button in current {
+        input.press(button);
+    }
+    input
+}
+
+/// Writes the recording once the session ends, by leaving the Game state or closing the game
+pub fn save_recording(recorder: Option<ResMut<ReplayRecorder>>) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    let Some(path) = recorder.path.clone() else {
+        return;
+    };
+    if let Some(replay) = recorder.replay.take() {
+        match replay.save(&path) {
+            Ok(()) => info!(
+                frames = replay.frames.len(),
+                path = %path.display(),
+                "Saved replay"
+            ),
+            Err(error) => error!(path = %path.display(), "Couldn't save replay: {error}"),
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
+use bevy::diagnostic::Diagnostics;
 use bevy::ecs::world;
 use bevy::render::camera::{self, RenderTarget, Viewport};
 use bevy::render::render_resource::{
@@ -9,16 +10,34 @@ use bevy::render::texture::BevyDefault;
 use bevy::render::view::RenderLayers;
 use bevy::sprite::MaterialMesh2dBundle;
 use bevy::{asset, prelude::*};
-use bevy_ecs_ldtk::prelude::LayerInstance;
-use bevy_ecs_ldtk::{GridCoords, LdtkLevel, LdtkWorldBundle, LevelSelection};
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use bevy_ecs_ldtk::{
+    EntityInstance, GridCoords, LdtkAsset, LdtkLevel, LdtkWorldBundle, LevelSelection,
+};
 use bevy_rapier2d::prelude::*;
 use iyes_loopless::state::NextState;
 use sark_pathfinding::*;
 
 use crate::components::{
-    ColliderBundle, Game, GameState, InGame, Player, PostProcessingMaterial, Wall,
+    Game, GameState, InGame, MapPath, Player, PlayerBundle, PlayerStart, PostProcessingMaterial,
 };
+use crate::logging::{record_time, PATHFINDING_TIME};
+use crate::states::game::camera::CameraFollow;
+use crate::states::game::colliders::ColliderSpec;
 use crate::states::game::components::*;
+use crate::states::game::console::Cheats;
+use crate::states::game::crowd::{claimed_cells, release_cells, reserve_cells};
+use crate::states::game::interaction::{
+    spawn_interaction_prompt, InteractEvent, Interactable, InteractionKind,
+};
+use crate::states::game::lighting::{
+    spawn_lighting_overlay, Illumination, LightMap, LightingMaterial,
+};
+use crate::states::game::objectives::sees_player;
+use crate::states::game::post_processing::{identity_lut, IdentityLut, PostProcessingStack};
+use crate::states::game::terrain::{AgentClass, TerrainMap, TerrainType};
+use crate::states::game::transitions::start_room_transition;
+use crate::tuning::Tuning;
 
 pub fn input(
     mut commands: Commands,
@@ -40,8 +59,10 @@ pub fn aiming(
     // Games typically only have one window (the primary window).
     // For multi-window applications, you need to use a specific window ID here.
     // get the camera info and transform
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = q_camera.single();
+    // The camera is spawned on entering the Game state, after this runs on the first frame
+    let Ok((camera, camera_transform)) = q_camera.get_single() else {
+        return;
+    };
 
     // get the window that the camera is displaying to (or the primary window)
     let windows = if let RenderTarget::Window(id) = camera.target {
@@ -75,11 +96,51 @@ pub fn unhide_cursor(mut windows: ResMut<Windows>) {
     windows.set_cursor_visibility(true);
 }
 
+/// The game draws its own cursor inside the low res view, see `cursor`
+pub fn hide_cursor(mut windows: ResMut<Windows>) {
+    let windows = windows.get_primary_mut().unwrap();
+    windows.set_cursor_visibility(false);
+}
+
+/// Spawns the player at the PlayerStart of the selected level once it has loaded
+pub fn spawn_player(
+    mut commands: Commands,
+    start_query: Query<(&Transform, &Parent, &EntityInstance), Added<PlayerStart>>,
+    level_query: Query<(&Handle<LdtkLevel>, &Transform), Without<PlayerStart>>,
+    player_query: Query<(), (With<Player>, Without<ItemTag>)>,
+    level_selection: Res<LevelSelection>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !player_query.is_empty() {
+        return;
+    }
+    for (start_transform, parent, entity_instance) in &start_query {
+        if let Ok((level_handle, level_transform)) = level_query.get(parent.get()) {
+            if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
+                if level_selection.is_match(&0, &ldtk_level.level) {
+                    commands.spawn(PlayerBundle::new(
+                        level_transform.translation + start_transform.translation,
+                        ColliderSpec::from(entity_instance.clone()),
+                        &asset_server,
+                    ));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn update_level_selection(
+    mut commands: Commands,
     level_query: Query<(&Handle<LdtkLevel>, &Transform), Without<Player>>,
     player_query: Query<&Transform, With<Player>>,
     mut level_selection: ResMut<LevelSelection>,
     ldtk_levels: Res<Assets<LdtkLevel>>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    mut path_map_initialized: ResMut<PathInit>,
+    tuning: Res<Tuning>,
 ) {
     for (level_handle, level_transform) in &level_query {
         if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
@@ -97,7 +158,23 @@ pub fn update_level_selection(
                     && player_transform.translation.y > level_bounds.min.y
                     && !level_selection.is_match(&0, &ldtk_level.level)
                 {
+                    let previous_level = level_query
+                        .iter()
+                        .filter_map(|(handle, _)| ldtk_levels.get(handle))
+                        .find(|previous| level_selection.is_match(&0, &previous.level));
+                    if let Some(previous_level) = previous_level {
+                        start_room_transition(
+                            &mut commands,
+                            &mut rapier_configuration,
+                            &tuning,
+                            &previous_level.level,
+                            &ldtk_level.level,
+                        );
+                    }
                     *level_selection = LevelSelection::Iid(ldtk_level.level.iid.clone());
+                    // Neighbouring rooms are already loaded, so no level spawns to set off
+                    // `reload_map`, the nav grid has to be built for the new room here
+                    path_map_initialized.0 = false;
                 }
             }
         }
@@ -154,46 +231,57 @@ pub fn scale_render_image(
 }
 
 pub fn move_player(
-    mut commands: Commands,
+    mut query: Query<(&Transform, &mut Velocity), With<Player>>,
     input: Res<Input<KeyCode>>,
-    mut query: Query<(&mut Velocity), With<Player>>,
-    colliding_query: Query<&CollidingEntities, With<Player>>,
-    player_query: Query<(Entity, &Items, &Transform), With<Player>>,
-    items_query: Query<(Entity, &Items), (With<ItemTag>, With<Collider>)>,
-    asset_server: Res<AssetServer>,
+    terrain_map: Res<TerrainMap>,
+    tuning: Res<Tuning>,
 ) {
-    for (mut velocity) in &mut query {
+    for (transform, mut velocity) in &mut query {
+        let speed = tuning.player_speed * terrain_map.speed_at(transform.translation.truncate());
         let right = if input.pressed(KeyCode::D) { 1. } else { 0. };
         let left = if input.pressed(KeyCode::A) { 1. } else { 0. };
 
-        velocity.linvel.x = (right - left) * 200.;
+        velocity.linvel.x = (right - left) * speed;
         let up = if input.pressed(KeyCode::W) { 1. } else { 0. };
         let down = if input.pressed(KeyCode::S) { 1. } else { 0. };
 
-        velocity.linvel.y = (up - down) * 200.;
+        velocity.linvel.y = (up - down) * speed;
     }
-    if input.just_pressed(KeyCode::E) {
-        for collider_entities in colliding_query.iter() {
-            for collider in collider_entities.iter() {
-                if let Ok((item_entity, item_type)) = items_query.get(collider) {
-                    if let Ok((player, player_item, player_transform)) = player_query.get_single() {
-                        switch_item(
-                            item_type,
-                            &mut commands,
-                            player,
-                            player_item,
-                            item_entity,
-                            player_transform,
-                            &asset_server,
-                        );
-                        return;
-                    }
-                }
-            }
+}
+
+pub fn pick_up_items(
+    mut commands: Commands,
+    mut interact_events: EventReader<InteractEvent>,
+    player_query: Query<(&Items, &Transform), With<Player>>,
+    items_query: Query<&Items, With<ItemTag>>,
+    asset_server: Res<AssetServer>,
+    tuning: Res<Tuning>,
+) {
+    for event in interact_events.iter() {
+        if event.kind != InteractionKind::PickUp {
+            continue;
+        }
+        if let (Ok(item_type), Ok((player_item, player_transform))) =
+            (items_query.get(event.target), player_query.get(event.actor))
+        {
+            switch_item(
+                item_type,
+                &mut commands,
+                event.actor,
+                player_item,
+                event.target,
+                player_transform,
+                &asset_server,
+                &tuning,
+            );
+            // The swap only lands when the commands are applied, a second pickup this frame
+            // would drop the item the player held before the first one again
+            break;
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn switch_item(
     item_type: &Items,
     mut commands: &mut Commands,
@@ -202,9 +290,10 @@ fn switch_item(
     item_entity: Entity,
     player_transform: &Transform,
     asset_server: &Res<AssetServer>,
+    tuning: &Tuning,
 ) {
     match item_type {
-        Items::None => println!("Not implemented"),
+        Items::None => warn!("Picking up nothing isn't implemented"),
         _ => {
             match player_item {
                 Items::GlassBottle => {
@@ -224,14 +313,19 @@ fn switch_item(
                                 texture: asset_server.load("glass_bottle.png"),
                                 ..Default::default()
                             },
+                            interaction: Interactable::pick_up(Items::GlassBottle),
+                            collider: ColliderSpec::item(tuning.item_sensor_half_size * 2.0),
                             ..Default::default()
                         },))
                         .insert(Items::GlassBottle);
                 }
                 Items::None => {
-                    println!("No Item!");
+                    debug!(item = item_type.name(), "Picked up with empty hands");
                 }
-                _ => println!("Todo"),
+                _ => warn!(
+                    item = player_item.name(),
+                    "Dropping this item isn't implemented"
+                ),
             }
             commands.get_entity(player).unwrap().insert(*item_type);
             commands.get_entity(item_entity).unwrap().despawn();
@@ -246,6 +340,7 @@ pub fn show_held_item(
     player_query: Query<(Entity, &Items), With<Player>>,
     previous_child_query: Query<(Entity), (With<ItemTag>, Without<Collider>)>,
     last_item: Res<Items>,
+    tuning: Res<Tuning>,
 ) {
     if let Ok((player, player_item)) = player_query.get_single() {
         if last_item.into_inner() != player_item {
@@ -264,7 +359,7 @@ pub fn show_held_item(
             let child_sprite = commands.spawn((
                 SpriteBundle {
                     transform: Transform::from_translation(Vec3 {
-                        x: 8.0,
+                        x: tuning.held_item_offset,
                         y: 0.0,
                         z: 4.0,
                     }),
@@ -278,58 +373,7 @@ pub fn show_held_item(
                 .get_entity(player)
                 .unwrap()
                 .add_child(child_sprite.id());
-            println!("Showing New Item");
-        }
-    }
-}
-
-pub fn camera_fit_inside_current_level(
-    mut camera_query: Query<
-        (
-            &mut bevy::render::camera::OrthographicProjection,
-            &mut Transform,
-        ),
-        (Without<Player>, With<CameraTag>),
-    >,
-    player_query: Query<&Transform, (With<Player>, Without<ItemTag>)>,
-    level_query: Query<
-        (&Transform, &Handle<LdtkLevel>),
-        (Without<OrthographicProjection>, Without<Player>),
-    >,
-    level_selection: Res<LevelSelection>,
-    ldtk_levels: Res<Assets<LdtkLevel>>,
-) {
-    if let Ok(Transform {
-        translation: player_translation,
-        ..
-    }) = player_query.get_single()
-    {
-        let player_translation = *player_translation;
-
-        let (mut orthographic_projection, mut camera_transform) = camera_query.single_mut();
-        for (level_transform, level_handle) in &level_query {
-            if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
-                let level = &ldtk_level.level;
-                if level_selection.is_match(&0, level) {
-                    orthographic_projection.scaling_mode = bevy::render::camera::ScalingMode::None;
-                    orthographic_projection.bottom = 0.;
-                    orthographic_projection.left = 0.;
-
-                    orthographic_projection.right = SCREEN_HEIGHT as f32 * ASPECT_RATIO;
-                    camera_transform.translation.x = (player_translation.x
-                        - level_transform.translation.x
-                        - orthographic_projection.right / 2.)
-                        .clamp(0., level.px_wid as f32 - orthographic_projection.right);
-                    orthographic_projection.top = SCREEN_WIDTH as f32 / ASPECT_RATIO;
-                    camera_transform.translation.y = (player_translation.y
-                        - level_transform.translation.y
-                        - orthographic_projection.top / 2.)
-                        .clamp(0., level.px_hei as f32 - orthographic_projection.top);
-
-                    camera_transform.translation.x += level_transform.translation.x;
-                    camera_transform.translation.y += level_transform.translation.y;
-                }
-            }
+            debug!(item = player_item.name(), "Showing held item");
         }
     }
 }
@@ -337,52 +381,71 @@ pub fn camera_fit_inside_current_level(
 pub fn setup(
     mut commands: Commands,
     mut ingame: ResMut<InGame>,
+    map_path: Res<MapPath>,
     asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut post_processing_materials: ResMut<Assets<PostProcessingMaterial>>,
-    _materials: ResMut<Assets<StandardMaterial>>,
-    mut images: ResMut<Assets<Image>>,
 ) {
     if ingame.0 {
     } else {
         ingame.0 = true;
-        let size = Extent3d {
-            width: SCREEN_WIDTH,
-            height: SCREEN_HEIGHT,
-            ..default()
-        };
-
-        // This is the texture that will be rendered to.
-        let mut image = Image {
-            texture_descriptor: TextureDescriptor {
-                label: None,
-                size,
-                dimension: TextureDimension::D2,
-                format: TextureFormat::bevy_default(),
-                mip_level_count: 1,
-                sample_count: 1,
-                usage: TextureUsages::TEXTURE_BINDING
-                    | TextureUsages::COPY_DST
-                    | TextureUsages::RENDER_ATTACHMENT,
-            },
-            ..default()
-        };
-
-        // fill image.data with zeroes
-        image.resize(size);
-
-        let image_handle = images.add(image);
-
         commands.spawn((
             Game,
             LdtkWorldBundle {
-                ldtk_handle: asset_server.load("Map.ldtk"),
+                ldtk_handle: asset_server.load(&map_path.0),
                 ..Default::default()
             },
         ));
-        commands.spawn((
+    }
+}
+
+/// Spawns the cameras, render image and overlays. Not used by headless simulations.
+#[allow(clippy::too_many_arguments)]
+pub fn setup_view(
+    mut commands: Commands,
+    camera_query: Query<(), With<CameraTag>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut post_processing_materials: ResMut<Assets<PostProcessingMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    post_processing_stack: Res<PostProcessingStack>,
+    mut lighting_materials: ResMut<Assets<LightingMaterial>>,
+    light_map: Res<LightMap>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    if !camera_query.is_empty() {
+        return;
+    }
+    let size = Extent3d {
+        width: SCREEN_WIDTH,
+        height: SCREEN_HEIGHT,
+        ..default()
+    };
+
+    // This is the texture that will be rendered to.
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::bevy_default(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+
+    // fill image.data with zeroes
+    image.resize(size);
+
+    let image_handle = images.add(image);
+
+    commands
+        .spawn((
             Game,
             CameraTag,
+            CameraFollow::default(),
             UiCameraConfig { show_ui: false },
             Camera2dBundle {
                 camera: Camera {
@@ -391,289 +454,94 @@ pub fn setup(
                 },
                 ..Default::default()
             },
-        ));
-        let post_processing_pass_layer =
-            RenderLayers::layer((RenderLayers::TOTAL_LAYERS - 1) as u8);
-
-        let quad_handle = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(
-            size.width as f32,
-            size.height as f32,
-        ))));
-
-        // This material has the texture that has been rendered.
-        let material_handle = post_processing_materials.add(PostProcessingMaterial {
-            source_image: image_handle,
+        ))
+        .with_children(|camera| {
+            spawn_lighting_overlay(
+                camera,
+                &mut meshes,
+                &mut lighting_materials,
+                &mut images,
+                &light_map,
+            )
         });
+    let post_processing_pass_layer = RenderLayers::layer((RenderLayers::TOTAL_LAYERS - 1) as u8);
+
+    let quad_handle = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(
+        size.width as f32,
+        size.height as f32,
+    ))));
+
+    let lut_handle = images.add(identity_lut());
+    commands.insert_resource(IdentityLut(lut_handle.clone()));
+
+    // This material has the texture that has been rendered.
+    let material_handle = post_processing_materials.add(PostProcessingMaterial {
+        settings: post_processing_stack.settings(),
+        source_image: image_handle,
+        lut: post_processing_stack
+            .color_grade
+            .lut
+            .clone()
+            .unwrap_or(lut_handle),
+    });
 
-        // Post processing 2d quad, with material using the render texture done by the main camera, with a custom shader.
-        commands.spawn((
-            MaterialMesh2dBundle {
-                mesh: quad_handle.into(),
-                material: material_handle,
-                transform: Transform {
-                    translation: Vec3::new(0.0, 0.0, 1.5),
-                    ..default()
-                },
+    // The cursor is drawn inside the render image so it gets pixelated with everything else,
+    // in front of the lighting overlay so it is never darkened
+    let cursor_atlas = texture_atlases.add(TextureAtlas::from_grid(
+        asset_server.load("cursor.png"),
+        Vec2::new(7.0, 7.0),
+        3,
+        1,
+        None,
+        None,
+    ));
+    commands.spawn((
+        Game,
+        CursorTag,
+        CursorState::Default,
+        SpriteSheetBundle {
+            texture_atlas: cursor_atlas,
+            transform: Transform::from_xyz(0.0, 0.0, 999.0),
+            ..default()
+        },
+    ));
+    spawn_interaction_prompt(&mut commands, &asset_server);
+
+    // Post processing 2d quad, with material using the render texture done by the main camera, with a custom shader.
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: quad_handle.into(),
+            material: material_handle,
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 1.5),
                 ..default()
             },
-            post_processing_pass_layer,
-            RenderImage,
-            Game,
-        ));
-
-        commands.spawn((
-            Camera2dBundle {
-                camera: Camera {
-                    viewport: Some(Viewport {
-                        physical_size: UVec2 {
-                            x: SCREEN_WIDTH,
-                            y: SCREEN_HEIGHT,
-                        },
-                        ..Default::default()
-                    }),
-                    // renders after the first main camera which has default value: 0.
-                    priority: 1,
-                    ..default()
-                },
-                ..Camera2dBundle::default()
+            ..default()
+        },
+        post_processing_pass_layer,
+        RenderImage,
+        Game,
+    ));
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                viewport: Some(Viewport {
+                    physical_size: UVec2 {
+                        x: SCREEN_WIDTH,
+                        y: SCREEN_HEIGHT,
+                    },
+                    ..Default::default()
+                }),
+                // renders after the first main camera which has default value: 0.
+                priority: 1,
+                ..default()
             },
-            post_processing_pass_layer,
-            Game,
-        ));
-    }
-}
-
-/// Spawns heron collisions for the walls of a level
-///
-/// You could just insert a ColliderBundle in to the WallBundle,
-/// but this spawns a different collider for EVERY wall tile.
-/// This approach leads to bad performance.
-///
-/// Instead, by flagging the wall tiles and spawning the collisions later,
-/// we can minimize the amount of colliding entities.
-///
-/// The algorithm used here is a nice compromise between simplicity, speed,
-/// and a small number of rectangle colliders.
-/// In basic terms, it will:
-/// 1. consider where the walls are
-/// 2. combine wall tiles into flat "plates" in each individual row
-/// 3. combine the plates into rectangles across multiple rows wherever possible
-/// 4. spawn colliders for each rectangle
-pub fn spawn_wall_collision(
-    mut commands: Commands,
-    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
-    parent_query: Query<&Parent, Without<Wall>>,
-    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
-    levels: Res<Assets<LdtkLevel>>,
-) {
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn wall collisions
-    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
-    struct Plate {
-        left: i32,
-        right: i32,
-    }
-
-    /// A simple rectangle type representing a wall of any size
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
-    }
-
-    // Consider where the walls are
-    // storing them as GridCoords in a HashSet for quick, easy lookup
-    //
-    // The key of this map will be the entity of the level the wall belongs to.
-    // This has two consequences in the resulting collision entities:
-    // 1. it forces the walls to be split along level boundaries
-    // 2. it lets us easily add the collision entities as children of the appropriate level entity
-    let mut level_to_wall_locations: HashMap<Entity, HashSet<GridCoords>> = HashMap::new();
-
-    wall_query.for_each(|(&grid_coords, parent)| {
-        // An intgrid tile's direct parent will be a layer entity, not the level entity
-        // To get the level entity, you need the tile's grandparent.
-        // This is where parent_query comes in.
-        if let Ok(grandparent) = parent_query.get(parent.get()) {
-            level_to_wall_locations
-                .entry(grandparent.get())
-                .or_default()
-                .insert(grid_coords);
-        }
-    });
-
-    if !wall_query.is_empty() {
-        level_query.for_each(|(level_entity, level_handle)| {
-            if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
-                let level = levels
-                    .get(level_handle)
-                    .expect("Level should be loaded by this point");
-
-                let LayerInstance {
-                    c_wid: width,
-                    c_hei: height,
-                    grid_size,
-                    ..
-                } = level
-                    .level
-                    .layer_instances
-                    .clone()
-                    .expect("Level asset should have layers")[0];
-
-                // combine wall tiles into flat "plates" in each individual row
-                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-                for y in 0..height {
-                    let mut row_plates: Vec<Plate> = Vec::new();
-                    let mut plate_start = None;
-
-                    // + 1 to the width so the algorithm "terminates" plates that touch the right edge
-                    for x in 0..width + 1 {
-                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
-                            (Some(s), false) => {
-                                row_plates.push(Plate {
-                                    left: s,
-                                    right: x - 1,
-                                });
-                                plate_start = None;
-                            }
-                            (None, true) => plate_start = Some(x),
-                            _ => (),
-                        }
-                    }
-
-                    plate_stack.push(row_plates);
-                }
-
-                // combine "plates" into rectangles across multiple rows
-                let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
-                let mut prev_row: Vec<Plate> = Vec::new();
-                let mut wall_rects: Vec<Rect> = Vec::new();
-
-                // an extra empty row so the algorithm "finishes" the rects that touch the top edge
-                plate_stack.push(Vec::new());
-
-                for (y, current_row) in plate_stack.into_iter().enumerate() {
-                    for prev_plate in &prev_row {
-                        if !current_row.contains(prev_plate) {
-                            // remove the finished rect so that the same plate in the future starts a new rect
-                            if let Some(rect) = rect_builder.remove(prev_plate) {
-                                wall_rects.push(rect);
-                            }
-                        }
-                    }
-                    for plate in &current_row {
-                        rect_builder
-                            .entry(plate.clone())
-                            .and_modify(|e| e.top += 1)
-                            .or_insert(Rect {
-                                bottom: y as i32,
-                                top: y as i32,
-                                left: plate.left,
-                                right: plate.right,
-                            });
-                    }
-                    prev_row = current_row;
-                }
-
-                commands.entity(level_entity).with_children(|level| {
-                    // Spawn colliders for every rectangle..
-                    // Making the collider a child of the level serves two purposes:
-                    // 1. Adjusts the transforms to be relative to the level for free
-                    // 2. the colliders will be despawned automatically when levels unload
-                    for wall_rect in wall_rects {
-                        level
-                            .spawn_empty()
-                            .insert(Collider::cuboid(
-                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                            ))
-                            .insert(RigidBody::Fixed)
-                            .insert(Friction::new(1.0))
-                            .insert(Transform::from_xyz(
-                                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                0.,
-                            ))
-                            .insert(GlobalTransform::default());
-                    }
-                });
-            }
-        });
-    }
-}
-
-pub fn fix_player_col(
-    mut commands: Commands,
-    player_query: Query<Entity, With<Player>>,
-    collider_query: Query<(&Collider), With<Player>>,
-) {
-    if let Ok(collider) = collider_query.get_single() {
-    } else {
-        if let Ok(player) = player_query.get_single() {
-            let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-            commands.get_entity(player).unwrap().insert(ColliderBundle {
-                collider: Collider::ball(8.),
-                rigid_body: RigidBody::Dynamic,
-                friction: Friction {
-                    coefficient: 0.0,
-                    combine_rule: CoefficientCombineRule::Min,
-                },
-                rotation_constraints,
-                ..Default::default()
-            });
-        }
-    }
-}
-
-pub fn fix_enemy_col(
-    mut commands: Commands,
-    main_enemy_query: Query<Entity, With<MainEnemy>>,
-    collider_query: Query<(&Collider), With<MainEnemy>>,
-) {
-    if let Ok(collider) = collider_query.get_single() {
-    } else {
-        if let Ok(enemy) = main_enemy_query.get_single() {
-            let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-            commands.get_entity(enemy).unwrap().insert(ColliderBundle {
-                collider: Collider::ball(8.),
-                rigid_body: RigidBody::Dynamic,
-                friction: Friction {
-                    coefficient: 0.0,
-                    combine_rule: CoefficientCombineRule::Min,
-                },
-                rotation_constraints,
-                ..Default::default()
-            });
-        }
-    }
-}
-
-pub fn add_item_col(
-    mut commands: Commands,
-    item_query: Query<Entity, (With<ItemTag>, Without<Player>)>,
-    collider_query: Query<(&Collider), With<ItemTag>>,
-) {
-    for item in item_query.iter() {
-        if let Ok(col) = collider_query.get(item) {
-        } else {
-            let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-            commands.get_entity(item).unwrap().insert(SensorBundle {
-                collider: Collider::cuboid(8., 8.),
-                sensor: Sensor,
-                rotation_constraints,
-                active_events: ActiveEvents::COLLISION_EVENTS,
-                ..Default::default()
-            });
-        }
-    }
+            ..Camera2dBundle::default()
+        },
+        post_processing_pass_layer,
+        Game,
+    ));
 }
 
 pub fn face_towards_cursor(
@@ -691,131 +559,285 @@ pub fn face_towards_cursor(
     }
 }
 
-pub fn cursor(mut commands: Commands, world_coords: Res<WorldMouseCoords>) {}
+pub fn cursor(
+    windows: Res<Windows>,
+    world_coords: Res<WorldMouseCoords>,
+    mut cursor_query: Query<
+        (
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &mut Visibility,
+            &mut CursorState,
+        ),
+        With<CursorTag>,
+    >,
+    camera_query: Query<&Transform, (With<CameraTag>, Without<CursorTag>)>,
+    player_query: Query<&Items, With<Player>>,
+    items_query: Query<(&Collider, &GlobalTransform), With<ItemTag>>,
+) {
+    if let Ok((mut cursor_transform, mut sprite, mut visibility, mut state)) =
+        cursor_query.get_single_mut()
+    {
+        visibility.is_visible = windows
+            .get_primary()
+            .map_or(false, |window| window.cursor_position().is_some());
+
+        // Snap to the pixels of the render image, which are relative to the camera
+        let camera_translation = camera_query
+            .get_single()
+            .map_or(Vec3::ZERO, |transform| transform.translation);
+        let snapped = (world_coords.0 - camera_translation.truncate()).floor()
+            + camera_translation.truncate()
+            + Vec2::splat(0.5);
+        cursor_transform.translation.x = snapped.x;
+        cursor_transform.translation.y = snapped.y;
+
+        let hovering_item = items_query.iter().any(|(collider, item_transform)| {
+            collider.contains_point(item_transform.translation().truncate(), 0.0, world_coords.0)
+        });
+        let holding_throwable = player_query
+            .get_single()
+            .map_or(false, |item| item.is_throwable());
+        *state = if hovering_item {
+            CursorState::Interact
+        } else if holding_throwable {
+            CursorState::Aim
+        } else {
+            CursorState::Default
+        };
+        sprite.index = state.atlas_index();
+    }
+}
+
+/// bevy_ecs_ldtk respawns the levels when Map.ldtk is saved, the nav grid has to follow.
+/// The player isn't part of a level so they keep their position, item and keys.
+pub fn reload_map(
+    mut ldtk_events: EventReader<AssetEvent<LdtkAsset>>,
+    spawned_levels: Query<(), Added<Handle<LdtkLevel>>>,
+    mut path_map_initialized: ResMut<PathInit>,
+) {
+    for event in ldtk_events.iter() {
+        if let AssetEvent::Modified { .. } = event {
+            path_map_initialized.0 = false;
+        }
+    }
+    // The old level can still be around the frame Map.ldtk changes,
+    // build the nav grid again once its replacement has spawned
+    if !spawned_levels.is_empty() {
+        path_map_initialized.0 = false;
+    }
+}
 
+/// Builds the nav grid of the current level from all of its terrain tiles,
+/// once they have spawned. After that `rebuild_wall_chunks` patches the cells that change.
 pub fn create_collision_map(
-    mut commands: Commands,
-    wall_query: Query<(&GridCoords), Added<Wall>>,
+    terrain_query: Query<(&GridCoords, &TerrainType, &Parent)>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
     mut path_map: ResMut<PathfindingMap>,
-    mut astar_map: ResMut<AstarMap>,
     mut path_map_initialized: ResMut<PathInit>,
-    level_query: Query<(&Handle<LdtkLevel>, &Transform), Without<Player>>,
-    ldtk_levels: Res<Assets<LdtkLevel>>,
 ) {
-    if path_map_initialized.0 != true {
-        for (level_handle, level_transform) in &level_query {
-            if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
-                if let Some(level) = ldtk_levels.get(level_handle) {
-                    let grid_size_y = level.level.px_hei / 16;
-                    let grid_size_x = level.level.px_wid / 16;
-                    let mut map = PathMap2d::new([
-                        grid_size_x.try_into().unwrap(),
-                        grid_size_y.try_into().unwrap(),
-                    ]);
-                    let astarm: AStar<[i32; 2]> = AStar::from_size([
-                        grid_size_x.try_into().unwrap(),
-                        grid_size_y.try_into().unwrap(),
-                    ]);
-                    for wall in wall_query.iter() {
-                        map.set_obstacle([wall.x, wall.y], true);
-                        path_map_initialized.0 = true;
-                    }
-                    commands.insert_resource(PathfindingMap { path_map: map });
-                    commands.insert_resource(AstarMap { astar: astarm });
-                }
-            }
+    if path_map_initialized.0 {
+        return;
+    }
+    let Some(current_level) = level_query.iter().find_map(|(level_entity, level_handle)| {
+        ldtk_levels
+            .get(level_handle)
+            .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+            .map(|_| level_entity)
+    }) else {
+        return;
+    };
+
+    let mut map = None;
+    for (cell, terrain, parent) in &terrain_query {
+        // An intgrid tile's direct parent will be a layer entity, whose parent is the level
+        let Ok((layer, level)) = layer_query.get(parent.get()) else {
+            continue;
+        };
+        if level.get() != current_level {
+            continue;
+        }
+        let map =
+            map.get_or_insert_with(|| PathMap2d::new([layer.c_wid as u32, layer.c_hei as u32]));
+        if !terrain.walkable_by(AgentClass::Enemy) {
+            map.set_obstacle([cell.x, cell.y], true);
         }
     }
+    // Nothing to go on until the terrain of the level has spawned
+    if let Some(map) = map {
+        // Straight away rather than through commands, so `update_door_nav` can add
+        // the doors this frame
+        path_map.path_map = map;
+        path_map_initialized.0 = true;
+    }
 }
 
-pub fn main_enemy_move(
-    mut commands: Commands,
+/// Every enemy chases the player while it sees them, and otherwise walks its own path
+/// to its `Target`, where it last saw them or heard a noise. Without one it waits.
+/// With `Tuning::reserve_enemy_cells` each one paths around the cells the others claimed,
+/// so a group takes different routes where there are any, see `crowd::avoid_crowding` for the rest.
+#[allow(clippy::too_many_arguments)]
+pub fn move_enemies(
     mut path_map: ResMut<PathfindingMap>,
-    mut astar_map: ResMut<AstarMap>,
-    mut main_enemy_query: Query<
-        (Entity, &mut Transform, &mut Target, &mut TargetPath),
-        With<MainEnemy>,
+    mut enemy_query: Query<
+        (
+            Entity,
+            &Transform,
+            &mut TargetPath,
+            &mut Velocity,
+            &mut Pathfinder,
+            &mut Target,
+        ),
+        With<Enemy>,
     >,
-    mut velocity_query: Query<(&mut Velocity), With<MainEnemy>>,
-    level_query: Query<(&Handle<LdtkLevel>, &Transform), (Without<MainEnemy>, Without<Player>)>,
-    mut level_selection: ResMut<LevelSelection>,
+    level_query: Query<(&Handle<LdtkLevel>, &Transform), (Without<Enemy>, Without<Player>)>,
+    level_selection: Res<LevelSelection>,
     ldtk_levels: Res<Assets<LdtkLevel>>,
-    player_query: Query<&Transform, (With<Player>, Without<MainEnemy>, Without<ItemTag>)>,
+    player_query: Query<
+        (&Transform, &Illumination),
+        (With<Player>, Without<Enemy>, Without<ItemTag>),
+    >,
+    light_map: Res<LightMap>,
+    cheats: Res<Cheats>,
+    terrain_map: Res<TerrainMap>,
+    mut diagnostics: Option<ResMut<Diagnostics>>,
+    tuning: Res<Tuning>,
 ) {
-    if let Ok((enemy_entity, mut main_enemy_transform, mut enemy_target, mut enemy_path)) =
-        main_enemy_query.get_single_mut()
-    {
-        if let Ok(player_transform) = player_query.get_single() {
-            let mut enemy_location = [0, 0];
-            let mut player_location = [0, 0];
-            for (level_handle, level_transform) in &level_query {
-                if let Some(ldtk_level) = ldtk_levels.get(level_handle) {
-                    if level_selection.is_match(&0, &ldtk_level.level) {
-                        let level_location = level_transform.translation;
-                        let level_size = Vec2 {
-                            x: ldtk_level.level.px_hei as f32,
-                            y: ldtk_level.level.px_wid as f32,
-                        };
-                        enemy_location = convert_world_to_grid(
-                            &level_location,
-                            &level_size,
-                            &Vec2 {
-                                x: main_enemy_transform.translation.x,
-                                y: main_enemy_transform.translation.y,
-                            },
-                        );
-                        player_location = convert_world_to_grid(
-                            &level_location,
-                            &level_size,
-                            &Vec2 {
-                                x: player_transform.translation.x,
-                                y: player_transform.translation.y,
+    if cheats.ai_off {
+        for (_, _, _, mut velocity, _, _) in &mut enemy_query {
+            *velocity = Velocity::zero();
+        }
+        return;
+    }
+    let Ok((player_transform, illumination)) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.truncate();
+    let Some((level_location, level_size)) =
+        level_query
+            .iter()
+            .find_map(|(level_handle, level_transform)| {
+                ldtk_levels
+                    .get(level_handle)
+                    .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+                    .map(|ldtk_level| {
+                        (
+                            level_transform.translation,
+                            Vec2 {
+                                x: ldtk_level.level.px_wid as f32,
+                                y: ldtk_level.level.px_hei as f32,
                             },
-                        );
-                    }
-                }
-            }
+                        )
+                    })
+            })
+    else {
+        return;
+    };
+    let grid_size = [
+        path_map.path_map.width() as u32,
+        path_map.path_map.height() as u32,
+    ];
+
+    let claims: Vec<(Entity, [i32; 2])> = if tuning.reserve_enemy_cells {
+        enemy_query
+            .iter()
+            .flat_map(|(entity, transform, path, _, _, _)| {
+                let location = convert_world_to_grid(
+                    &level_location,
+                    &level_size,
+                    &transform.translation.truncate(),
+                );
+                claimed_cells(location, &path.0)
+                    .map(move |cell| (entity, cell))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
 
-            let backup_vector_path: Vec<[i32; 2]> = vec![[0, 0]];
-            enemy_path.0 = astar_map
-                .astar
-                .find_path(&path_map.path_map, enemy_location, player_location)
-                .unwrap_or_else(|| &&backup_vector_path)
-                .to_vec();
-            astar_map.astar.clear();
-            astar_map.astar = AStar::from_size([
-                path_map.path_map.width().try_into().unwrap(),
-                path_map.path_map.height().try_into().unwrap(),
-            ]);
-            if let Ok((mut velocity)) = velocity_query.get_single_mut() {
-                let right = if enemy_location[0] < enemy_path.0.get(1).unwrap_or(&enemy_location)[0]
-                {
-                    1.
-                } else {
-                    0.
-                };
-                let left = if enemy_location[0] > enemy_path.0.get(1).unwrap_or(&enemy_location)[0]
-                {
-                    1.
-                } else {
-                    0.
-                };
-
-                velocity.linvel.x = (right - left) * 100.;
-                let up = if enemy_location[1] < enemy_path.0.get(1).unwrap_or(&enemy_location)[1] {
-                    1.
-                } else {
-                    0.
-                };
-                let down = if enemy_location[1] > enemy_path.0.get(1).unwrap_or(&enemy_location)[1]
-                {
-                    1.
-                } else {
-                    0.
-                };
+    for (entity, enemy_transform, mut enemy_path, mut velocity, mut pathfinder, mut target) in
+        &mut enemy_query
+    {
+        let enemy_position = enemy_transform.translation.truncate();
+        let enemy_location = convert_world_to_grid(&level_location, &level_size, &enemy_position);
+        // Changes to the target made here don't set off the alert animation,
+        // that is only for hearing things, see `hear_noise`
+        if !cheats.god && sees_player(enemy_position, player_position, illumination.0, &light_map) {
+            target.bypass_change_detection().0 = Some(player_position);
+        }
+        let goal_location = target
+            .0
+            .map(|goal| convert_world_to_grid(&level_location, &level_size, &goal));
+        let Some(goal_location) = goal_location.filter(|goal| *goal != enemy_location) else {
+            // Nothing to look for, or nothing found where it looked
+            target.bypass_change_detection().0 = None;
+            enemy_path.0.clear();
+            velocity.linvel = Vec2::ZERO;
+            continue;
+        };
 
-                velocity.linvel.y = (up - down) * 100.;
-                // main_enemy_transform.rotate_z(up.atan2(down));
+        let pathfinding_span =
+            debug_span!("pathfinding", ?enemy_location, ?goal_location).entered();
+        let start = Instant::now();
+        // Everything reserved is released again before the next search,
+        // so to anything else the nav grid didn't change
+        let reserved = reserve_cells(
+            &mut path_map.bypass_change_detection().path_map,
+            claims
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, cell)| *cell),
+            &[enemy_location, goal_location],
+        );
+        let mut path = pathfinder
+            .astar(grid_size)
+            .find_path(&path_map.path_map, enemy_location, goal_location)
+            .map(|path| path.to_vec());
+        release_cells(&mut path_map.bypass_change_detection().path_map, &reserved);
+        if path.is_none() && !reserved.is_empty() {
+            // Boxed in by the others, queue up behind them instead
+            path = pathfinder
+                .astar(grid_size)
+                .find_path(&path_map.path_map, enemy_location, goal_location)
+                .map(|path| path.to_vec());
+        }
+        if path.is_none() {
+            // Can't get there, gives up on it
+            target.bypass_change_detection().0 = None;
+        }
+        enemy_path.0 = path.unwrap_or_default();
+        record_time(&mut diagnostics, PATHFINDING_TIME, start);
+        drop(pathfinding_span);
+
+        let speed =
+            tuning.enemy_speed * terrain_map.speed_at(enemy_transform.translation.truncate());
+        let next = enemy_path.0.get(1).copied().unwrap_or(enemy_location);
+        let right = if enemy_location[0] < next[0] { 1. } else { 0. };
+        let left = if enemy_location[0] > next[0] { 1. } else { 0. };
+        velocity.linvel.x = (right - left) * speed;
+        let up = if enemy_location[1] < next[1] { 1. } else { 0. };
+        let down = if enemy_location[1] > next[1] { 1. } else { 0. };
+        velocity.linvel.y = (up - down) * speed;
+    }
+}
+
+pub fn hear_noise(
+    mut noise_events: EventReader<NoiseEvent>,
+    mut enemy_query: Query<(&Transform, &mut Target), With<Enemy>>,
+) {
+    for noise in noise_events.iter() {
+        for (enemy_transform, mut target) in &mut enemy_query {
+            if enemy_transform
+                .translation
+                .truncate()
+                .distance(noise.position)
+                <= noise.loudness
+            {
+                target.0 = Some(noise.position);
             }
         }
     }
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::{LayerMetadata, RegisterLdtkObjects};
+use bevy_ecs_ldtk::{GridCoords, IntGridCell, LdtkIntCell, LdtkLevel, LevelSelection};
+use bevy_rapier2d::prelude::{CollisionGroups, Group};
+
+use crate::components::Player;
+use crate::states::game::colliders::{ENEMY_GROUP, PLAYER_GROUP};
+use crate::states::game::components::{ItemTag, NoiseEvent};
+use crate::states::game::wall_chunks::TerrainCells;
+
+/// Pixels the player walks between two footsteps
+const STEP_LENGTH: f32 = 16.0;
+
+/// Which IntGrid value of the LDtk levels is which terrain. Only these values spawn anything.
+pub const TERRAIN_VALUES: [(i32, TerrainType); 6] = [
+    (1, TerrainType::Wall),
+    (2, TerrainType::Bush),
+    (3, TerrainType::LowCover),
+    (4, TerrainType::Water),
+    (5, TerrainType::GlassFloor),
+    (6, TerrainType::Pit),
+];
+
+/// Who moves across the terrain, each can be allowed on different kinds of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AgentClass {
+    Player,
+    Enemy,
+}
+
+impl AgentClass {
+    pub const ALL: [AgentClass; 2] = [AgentClass::Player, AgentClass::Enemy];
+
+    pub fn group(&self) -> Group {
+        match self {
+            AgentClass::Player => PLAYER_GROUP,
+            AgentClass::Enemy => ENEMY_GROUP,
+        }
+    }
+}
+
+pub struct TerrainRules {
+    /// Blocks light and everything physical, like walls
+    pub opaque: bool,
+    /// Who can walk on it, colliders block everyone else and enemies path around it
+    pub walkable_by: &'static [AgentClass],
+    /// Multiplies the speed of whoever walks on it
+    pub speed: f32,
+    /// How far away enemies hear the player step on it, in pixels
+    pub footstep_noise: f32,
+}
+
+/// The terrain of an IntGrid cell, see `TERRAIN_VALUES`
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TerrainType {
+    #[default]
+    Wall,
+    /// Slows everyone down and rustles
+    Bush,
+    /// Blocks movement, but not light
+    LowCover,
+    /// Enemies won't go in, the player wades through slowly and loudly
+    Water,
+    /// Crunches loudly underfoot
+    GlassFloor,
+    Pit,
+}
+
+impl TerrainType {
+    pub fn from_value(value: i32) -> Option<TerrainType> {
+        TERRAIN_VALUES
+            .iter()
+            .find(|(terrain_value, _)| *terrain_value == value)
+            .map(|(_, terrain)| *terrain)
+    }
+
+    pub fn rules(&self) -> TerrainRules {
+        match self {
+            TerrainType::Wall => TerrainRules {
+                opaque: true,
+                walkable_by: &[],
+                speed: 1.0,
+                footstep_noise: 0.0,
+            },
+            TerrainType::Bush => TerrainRules {
+                opaque: false,
+                walkable_by: &[AgentClass::Player, AgentClass::Enemy],
+                speed: 0.6,
+                footstep_noise: 24.0,
+            },
+            TerrainType::LowCover | TerrainType::Pit => TerrainRules {
+                opaque: false,
+                walkable_by: &[],
+                speed: 1.0,
+                footstep_noise: 0.0,
+            },
+            TerrainType::Water => TerrainRules {
+                opaque: false,
+                walkable_by: &[AgentClass::Player],
+                speed: 0.5,
+                footstep_noise: 64.0,
+            },
+            TerrainType::GlassFloor => TerrainRules {
+                opaque: false,
+                walkable_by: &[AgentClass::Player, AgentClass::Enemy],
+                speed: 1.0,
+                footstep_noise: 128.0,
+            },
+        }
+    }
+
+    pub fn walkable_by(&self, class: AgentClass) -> bool {
+        self.rules().walkable_by.contains(&class)
+    }
+
+    /// What the colliders of this terrain block, None if it doesn't need any
+    pub fn collision_groups(&self) -> Option<CollisionGroups> {
+        let rules = self.rules();
+        let filters = if rules.opaque {
+            Group::ALL
+        } else {
+            AgentClass::ALL
+                .into_iter()
+                .filter(|class| !rules.walkable_by.contains(class))
+                .fold(Group::NONE, |groups, class| groups | class.group())
+        };
+        (filters != Group::NONE).then(|| CollisionGroups::new(Group::ALL, filters))
+    }
+}
+
+impl From<IntGridCell> for TerrainType {
+    fn from(cell: IntGridCell) -> TerrainType {
+        TerrainType::from_value(cell.value).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
+pub struct TerrainBundle {
+    #[from_int_grid_cell]
+    pub terrain: TerrainType,
+}
+
+pub trait RegisterTerrain {
+    /// Spawns a `TerrainBundle` for every IntGrid value in `TERRAIN_VALUES`
+    fn register_terrain(&mut self) -> &mut Self;
+}
+
+impl RegisterTerrain for App {
+    fn register_terrain(&mut self) -> &mut Self {
+        for (value, _) in TERRAIN_VALUES {
+            self.register_ldtk_int_cell::<TerrainBundle>(value);
+        }
+        self
+    }
+}
+
+/// The terrain of the current level, for looking up what is underfoot
+#[derive(Resource, Default)]
+pub struct TerrainMap {
+    pub origin: Vec2,
+    pub grid_size: f32,
+    pub cells: HashMap<GridCoords, TerrainType>,
+}
+
+impl TerrainMap {
+    pub fn at(&self, point: Vec2) -> Option<TerrainType> {
+        if self.grid_size <= 0.0 {
+            return None;
+        }
+        let cell = ((point - self.origin) / self.grid_size).floor();
+        self.cells
+            .get(&GridCoords {
+                x: cell.x as i32,
+                y: cell.y as i32,
+            })
+            .copied()
+    }
+
+    /// Bare floor doesn't change anyone's speed
+    pub fn speed_at(&self, point: Vec2) -> f32 {
+        self.at(point).map_or(1.0, |terrain| terrain.rules().speed)
+    }
+}
+
+/// Rebuilds the terrain map whenever `rebuild_wall_chunks` sees terrain tiles spawn, despawn
+/// or change, or the player moves to another level
+pub fn update_terrain_map(
+    terrain_query: Query<(&GridCoords, &TerrainType, &Parent)>,
+    terrain_cells: Res<TerrainCells>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>, &Transform)>,
+    levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    mut terrain_map: ResMut<TerrainMap>,
+) {
+    if !terrain_cells.is_changed() && !level_selection.is_changed() {
+        return;
+    }
+    let Some((level_entity, level_transform)) =
+        level_query
+            .iter()
+            .find_map(|(level_entity, level_handle, level_transform)| {
+                levels
+                    .get(level_handle)
+                    .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+                    .map(|_| (level_entity, level_transform))
+            })
+    else {
+        return;
+    };
+
+    let mut grid_size = 0.0;
+    let mut cells = HashMap::new();
+    for (grid_coords, terrain, parent) in &terrain_query {
+        // IntGrid cells are children of their layer, which is a child of the level
+        if let Ok((layer, level)) = layer_query.get(parent.get()) {
+            if level.get() == level_entity {
+                grid_size = layer.grid_size as f32;
+                cells.insert(*grid_coords, *terrain);
+            }
+        }
+    }
+    *terrain_map = TerrainMap {
+        origin: level_transform.translation.truncate(),
+        grid_size,
+        cells,
+    };
+}
+
+/// The player makes a noise every step on loud terrain, enemies hear it through `hear_noise`
+pub fn footsteps(
+    player_query: Query<&Transform, (With<Player>, Without<ItemTag>)>,
+    terrain_map: Res<TerrainMap>,
+    mut noise_events: EventWriter<NoiseEvent>,
+    mut last_position: Local<Option<Vec2>>,
+    mut walked: Local<f32>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        *last_position = None;
+        return;
+    };
+    let position = player_transform.translation.truncate();
+    let moved = last_position.map_or(0.0, |last| last.distance(position));
+    *last_position = Some(position);
+    // Teleported or moved to another level rather than walked
+    if moved > STEP_LENGTH * 4.0 {
+        *walked = 0.0;
+        return;
+    }
+    *walked += moved;
+    if *walked < STEP_LENGTH {
+        return;
+    }
+    *walked -= STEP_LENGTH;
+    let loudness = terrain_map
+        .at(position)
+        .map_or(0.0, |terrain| terrain.rules().footstep_noise);
+    if loudness > 0.0 {
+        noise_events.send(NoiseEvent {
+            position,
+            loudness,
+            footstep: true,
+        });
+    }
+}
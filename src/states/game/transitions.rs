@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::Level;
+use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode};
+
+use crate::states::game::ldtk_fields::string_field;
+use crate::states::game::post_processing::PostProcessingStack;
+use crate::tuning::Tuning;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionStyle {
+    /// The camera slides over to the new room
+    Slide,
+    /// Fades to black and back in on the new room
+    Fade,
+    /// Jumps straight to the new room without pausing anything
+    Cut,
+}
+
+impl TransitionStyle {
+    pub fn from_name(name: &str) -> Option<TransitionStyle> {
+        match name.trim().to_lowercase().as_str() {
+            "slide" => Some(TransitionStyle::Slide),
+            "fade" => Some(TransitionStyle::Fade),
+            "cut" => Some(TransitionStyle::Cut),
+            _ => None,
+        }
+    }
+
+    /// Set on the levels in LDtk. A level's "Transitions" field lists the style used when
+    /// leaving it for a given room, e.g. "Level_1=fade, Level_2=cut". Otherwise the
+    /// "Transition" field of the room being entered is used, then `Slide`.
+    pub fn between(from: &Level, to: &Level) -> TransitionStyle {
+        let for_pair = string_field(&from.field_instances, "Transitions").and_then(|pairs| {
+            pairs.split(',').find_map(|pair| {
+                let (level, style) = pair.split_once('=')?;
+                (level.trim() == to.identifier)
+                    .then(|| TransitionStyle::from_name(style))
+                    .flatten()
+            })
+        });
+        for_pair
+            .or_else(|| {
+                string_field(&to.field_instances, "Transition")
+                    .and_then(|style| TransitionStyle::from_name(&style))
+            })
+            .unwrap_or(TransitionStyle::Slide)
+    }
+}
+
+/// Exists while the player is moving between rooms. Input, AI and physics are paused until it ends.
+#[derive(Resource, Debug)]
+pub struct RoomTransition {
+    pub style: TransitionStyle,
+    /// Seconds
+    pub elapsed: f32,
+    pub duration: f32,
+}
+
+impl RoomTransition {
+    /// From 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            (self.elapsed / self.duration).min(1.0)
+        }
+    }
+}
+
+/// Called when the player crosses into `to`, see `update_level_selection`
+pub fn start_room_transition(
+    commands: &mut Commands,
+    rapier_configuration: &mut RapierConfiguration,
+    tuning: &Tuning,
+    from: &Level,
+    to: &Level,
+) {
+    let style = TransitionStyle::between(from, to);
+    debug!(from = %from.identifier, to = %to.identifier, ?style, "Room transition");
+    if style == TransitionStyle::Cut {
+        return;
+    }
+    rapier_configuration.physics_pipeline_active = false;
+    commands.insert_resource(RoomTransition {
+        style,
+        elapsed: 0.0,
+        duration: tuning.room_transition_duration,
+    });
+}
+
+/// Counts in physics steps while they are fixed, so replays end transitions on the same frame
+pub fn advance_room_transition(
+    mut commands: Commands,
+    mut transition: ResMut<RoomTransition>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    time: Res<Time>,
+) {
+    transition.elapsed += match rapier_configuration.timestep_mode {
+        TimestepMode::Fixed { dt, .. } => dt,
+        _ => time.delta_seconds(),
+    };
+    if transition.progress() >= 1.0 {
+        rapier_configuration.physics_pipeline_active = true;
+        commands.remove_resource::<RoomTransition>();
+    }
+}
+
+/// A transition can't carry over into the next time the Game state starts,
+/// and neither can the physics it paused
+pub fn end_room_transition(
+    mut commands: Commands,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    commands.remove_resource::<RoomTransition>();
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+/// Black at the halfway point of a fade, when the camera cuts over to the new room
+pub fn fade_between_rooms(transition: Res<RoomTransition>, mut stack: ResMut<PostProcessingStack>) {
+    if transition.style == TransitionStyle::Fade {
+        stack.flash.color = Color::BLACK;
+        stack.flash.intensity = 1.0 - (transition.progress() * 2.0 - 1.0).abs();
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use bevy::diagnostic::Diagnostics;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LayerMetadata;
+use bevy_ecs_ldtk::{GridCoords, LdtkLevel, LevelSelection};
+use bevy_rapier2d::prelude::*;
+
+use crate::logging::{record_time, WALL_COLLISION_TIME};
+use crate::states::game::components::{PathInit, PathfindingMap};
+use crate::states::game::terrain::{AgentClass, TerrainType, TERRAIN_VALUES};
+
+/// Width and height of a chunk, in cells. Changing a cell only rebuilds the colliders of its chunk.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Marks a terrain collider with the chunk of the IntGrid layer it was merged from
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WallChunk {
+    pub layer: Entity,
+    pub chunk: IVec2,
+}
+
+pub fn chunk_of(coords: GridCoords) -> IVec2 {
+    IVec2::new(
+        coords.x.div_euclid(CHUNK_SIZE),
+        coords.y.div_euclid(CHUNK_SIZE),
+    )
+}
+
+/// Where every terrain tile is. Tiles that despawned can't be queried any more,
+/// this is how `rebuild_wall_chunks` still finds the chunk they were in.
+#[derive(Resource, Default)]
+pub struct TerrainCells {
+    tiles: HashMap<Entity, (Entity, GridCoords)>,
+    layers: HashMap<Entity, HashMap<GridCoords, TerrainType>>,
+}
+
+impl TerrainCells {
+    pub fn get(&self, layer: Entity, coords: GridCoords) -> Option<TerrainType> {
+        self.layers.get(&layer)?.get(&coords).copied()
+    }
+
+    pub fn has_layer(&self, layer: Entity) -> bool {
+        self.layers.contains_key(&layer)
+    }
+
+    /// Returns the layer and cell `tile` was in before, if it moved
+    fn insert(
+        &mut self,
+        tile: Entity,
+        layer: Entity,
+        coords: GridCoords,
+        terrain: TerrainType,
+    ) -> Option<(Entity, GridCoords)> {
+        let previous = self.remove(tile);
+        self.tiles.insert(tile, (layer, coords));
+        self.layers
+            .entry(layer)
+            .or_default()
+            .insert(coords, terrain);
+        previous.filter(|previous| *previous != (layer, coords))
+    }
+
+    fn remove(&mut self, tile: Entity) -> Option<(Entity, GridCoords)> {
+        let (layer, coords) = self.tiles.remove(&tile)?;
+        if let Some(cells) = self.layers.get_mut(&layer) {
+            cells.remove(&coords);
+            if cells.is_empty() {
+                self.layers.remove(&layer);
+            }
+        }
+        Some((layer, coords))
+    }
+}
+
+/// A rectangle of cells, all four sides inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridRect {
+    pub left: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub top: i32,
+}
+
+/// Merges the cells from `min` up to, but not including, `max` for which `solid` holds
+/// into as few rectangles as this simple approach manages. In basic terms, it will:
+/// 1. combine the solid cells into flat "plates" in each individual row
+/// 2. combine the plates into rectangles across multiple rows wherever possible
+pub fn merge_rects(min: IVec2, max: IVec2, solid: impl Fn(GridCoords) -> bool) -> Vec<GridRect> {
+    /// Represents a wide wall that is 1 tile tall
+    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+    struct Plate {
+        left: i32,
+        right: i32,
+    }
+
+    // combine solid cells into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in min.y..max.y {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+        for x in min.x..max.x + 1 {
+            match (plate_start, x < max.x && solid(GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rect_builder: HashMap<Plate, GridRect> = HashMap::new();
+    let mut prev_row: Vec<Plate> = Vec::new();
+    let mut rects: Vec<GridRect> = Vec::new();
+
+    // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+    plate_stack.push(Vec::new());
+
+    for (y, current_row) in (min.y..).zip(plate_stack) {
+        for prev_plate in &prev_row {
+            if !current_row.contains(prev_plate) {
+                // remove the finished rect so that the same plate in the future starts a new rect
+                if let Some(rect) = rect_builder.remove(prev_plate) {
+                    rects.push(rect);
+                }
+            }
+        }
+        for plate in &current_row {
+            rect_builder
+                .entry(plate.clone())
+                .and_modify(|e| e.top += 1)
+                .or_insert(GridRect {
+                    bottom: y,
+                    top: y,
+                    left: plate.left,
+                    right: plate.right,
+                });
+        }
+        prev_row = current_row;
+    }
+    rects
+}
+
+/// You could just insert a ColliderBundle in to the TerrainBundle,
+/// but this spawns a different collider for EVERY terrain tile.
+/// This approach leads to bad performance.
+///
+/// Instead, the tiles of each IntGrid layer are split into chunks of `CHUNK_SIZE` cells,
+/// and each type of solid terrain in a chunk is merged into a few rectangle colliders,
+/// blocking what that type of terrain blocks, see `merge_rects`.
+///
+/// When tiles spawn, despawn or change type, only the chunks they are in get their colliders
+/// despawned and merged again, and the nav grid cells they are on are updated to match.
+#[allow(clippy::too_many_arguments)]
+pub fn rebuild_wall_chunks(
+    mut commands: Commands,
+    changed_tiles: Query<
+        (Entity, &GridCoords, &TerrainType, &Parent),
+        Or<(Changed<TerrainType>, Changed<GridCoords>)>,
+    >,
+    removed_tiles: RemovedComponents<TerrainType>,
+    layer_query: Query<(&LayerMetadata, &Parent)>,
+    chunk_query: Query<(Entity, &WallChunk)>,
+    level_query: Query<(Entity, &Handle<LdtkLevel>)>,
+    ldtk_levels: Res<Assets<LdtkLevel>>,
+    level_selection: Res<LevelSelection>,
+    path_map_initialized: Res<PathInit>,
+    mut path_map: ResMut<PathfindingMap>,
+    mut terrain_cells: ResMut<TerrainCells>,
+    mut diagnostics: Option<ResMut<Diagnostics>>,
+) {
+    // The cells that changed, by the IntGrid layer they are in
+    let mut dirty_cells: HashSet<(Entity, GridCoords)> = HashSet::new();
+    for tile in removed_tiles.iter() {
+        dirty_cells.extend(terrain_cells.remove(tile));
+    }
+    for (tile, &grid_coords, &terrain, parent) in &changed_tiles {
+        // An intgrid tile's direct parent will be a layer entity
+        let layer_entity = parent.get();
+        dirty_cells.extend(terrain_cells.insert(tile, layer_entity, grid_coords, terrain));
+        dirty_cells.insert((layer_entity, grid_coords));
+    }
+    if dirty_cells.is_empty() {
+        return;
+    }
+
+    let _span = debug_span!("rebuild_wall_chunks", cells = dirty_cells.len()).entered();
+    let start = Instant::now();
+    // Sorted so colliders spawn in the same order every run, which replays rely on
+    let mut dirty_chunks: Vec<(Entity, IVec2)> = dirty_cells
+        .iter()
+        .map(|(layer_entity, grid_coords)| (*layer_entity, chunk_of(*grid_coords)))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    dirty_chunks.sort_by_key(|(layer_entity, chunk)| (*layer_entity, chunk.to_array()));
+
+    for (collider, wall_chunk) in &chunk_query {
+        if dirty_chunks.contains(&(wall_chunk.layer, wall_chunk.chunk)) {
+            commands.entity(collider).despawn_recursive();
+        }
+    }
+
+    for (layer_entity, chunk) in dirty_chunks {
+        // Gone along with its level, which took the colliders with it
+        let Ok((layer, level)) = layer_query.get(layer_entity) else {
+            continue;
+        };
+        let grid_size = layer.grid_size as f32;
+        let min = chunk * CHUNK_SIZE;
+        let max = (min + IVec2::splat(CHUNK_SIZE)).min(IVec2::new(layer.c_wid, layer.c_hei));
+
+        // Different types of terrain never merge, so each can block different things
+        for (_, terrain) in TERRAIN_VALUES {
+            let Some(collision_groups) = terrain.collision_groups() else {
+                continue;
+            };
+            let rects = merge_rects(min, max, |grid_coords| {
+                terrain_cells.get(layer_entity, grid_coords) == Some(terrain)
+            });
+
+            commands.entity(level.get()).with_children(|level| {
+                // Spawn colliders for every rectangle..
+                // Making the collider a child of the level serves two purposes:
+                // 1. Adjusts the transforms to be relative to the level for free
+                // 2. the colliders will be despawned automatically when levels unload
+                for rect in rects {
+                    level.spawn((
+                        Collider::cuboid(
+                            (rect.right - rect.left + 1) as f32 * grid_size / 2.,
+                            (rect.top - rect.bottom + 1) as f32 * grid_size / 2.,
+                        ),
+                        RigidBody::Fixed,
+                        Friction::new(1.0),
+                        collision_groups,
+                        Transform::from_xyz(
+                            (rect.left + rect.right + 1) as f32 * grid_size / 2.,
+                            (rect.bottom + rect.top + 1) as f32 * grid_size / 2.,
+                            0.,
+                        ),
+                        GlobalTransform::default(),
+                        WallChunk {
+                            layer: layer_entity,
+                            chunk,
+                        },
+                    ));
+                }
+            });
+        }
+    }
+
+    // `create_collision_map` builds the nav grid of a level once its terrain has spawned,
+    // after that changed cells of the current level are patched in here
+    if path_map_initialized.0 {
+        let current_level = level_query.iter().find_map(|(level_entity, level_handle)| {
+            ldtk_levels
+                .get(level_handle)
+                .filter(|ldtk_level| level_selection.is_match(&0, &ldtk_level.level))
+                .map(|_| level_entity)
+        });
+        let (width, height) = (
+            path_map.path_map.width() as i32,
+            path_map.path_map.height() as i32,
+        );
+        for (layer_entity, grid_coords) in dirty_cells {
+            let in_current_level = layer_query
+                .get(layer_entity)
+                .map_or(false, |(_, level)| Some(level.get()) == current_level);
+            let GridCoords { x, y } = grid_coords;
+            if !in_current_level || x < 0 || y < 0 || x >= width || y >= height {
+                continue;
+            }
+            let blocked = terrain_cells
+                .get(layer_entity, grid_coords)
+                .map_or(false, |terrain| !terrain.walkable_by(AgentClass::Enemy));
+            path_map.path_map.set_obstacle([x, y], blocked);
+        }
+    }
+    record_time(&mut diagnostics, WALL_COLLISION_TIME, start);
+}
+
+/// Leaving the game despawns the levels, forget their tiles too
+pub fn clear_terrain_cells(mut terrain_cells: ResMut<TerrainCells>) {
+    *terrain_cells = TerrainCells::default();
+}
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_ecs_ldtk::ldtk::{Level, Type};
+use bevy_ecs_ldtk::{LdtkAsset, LevelSelection};
+use iyes_loopless::state::NextState;
+
+use crate::components::{GameState, InGame, LevelSelect, MapPath};
+use crate::save::SaveData;
+
+const THUMBNAIL_HEIGHT: f32 = 96.0;
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+#[derive(Resource)]
+pub struct LevelSelectMap(pub Handle<LdtkAsset>);
+
+/// Filled with a button per level once Map.ldtk has loaded
+#[derive(Component)]
+pub struct LevelList;
+
+#[derive(Component)]
+pub struct LevelButton {
+    pub iid: String,
+}
+
+pub fn setup(mut commands: Commands, map_path: Res<MapPath>, asset_server: Res<AssetServer>) {
+    commands.spawn((LevelSelect, Camera2dBundle::default()));
+    commands.insert_resource(LevelSelectMap(asset_server.load(&map_path.0)));
+    commands.spawn((
+        LevelSelect,
+        LevelList,
+        NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                // Ui is y up, reverse so the first level is at the top
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+/// Draws the IntGrid layer of a level, one pixel per cell
+fn level_thumbnail(level: &Level) -> Option<Image> {
+    let int_grid = level
+        .layer_instances
+        .as_ref()?
+        .iter()
+        .find(|layer| layer.layer_instance_type == Type::IntGrid)?;
+    let floor = level.bg_color.as_rgba_u32().to_le_bytes();
+    let mut data = Vec::with_capacity(int_grid.int_grid_csv.len() * 4);
+    for value in &int_grid.int_grid_csv {
+        if *value == 0 {
+            data.extend_from_slice(&floor);
+        } else {
+            data.extend_from_slice(&[220, 220, 220, 255]);
+        }
+    }
+    Some(Image::new(
+        Extent3d {
+            width: int_grid.c_wid as u32,
+            height: int_grid.c_hei as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    ))
+}
+
+pub fn populate_level_list(
+    mut commands: Commands,
+    list_query: Query<Entity, (With<LevelList>, Without<Children>)>,
+    map: Res<LevelSelectMap>,
+    ldtk_assets: Res<Assets<LdtkAsset>>,
+    mut images: ResMut<Assets<Image>>,
+    save: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+) {
+    let (Ok(list), Some(ldtk_asset)) = (list_query.get_single(), ldtk_assets.get(&map.0)) else {
+        return;
+    };
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    commands.entity(list).with_children(|list| {
+        list.spawn(TextBundle::from_section(
+            "Select a level",
+            TextStyle {
+                font: font.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            },
+        ));
+        for level in ldtk_asset.iter_levels() {
+            list.spawn((
+                LevelButton {
+                    iid: level.iid.clone(),
+                },
+                ButtonBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(8.0)),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..default()
+                    },
+                    background_color: BUTTON_COLOR.into(),
+                    ..default()
+                },
+            ))
+            .with_children(|button| {
+                if let Some(thumbnail) = level_thumbnail(level) {
+                    let aspect = thumbnail.size().x / thumbnail.size().y;
+                    button.spawn(ImageBundle {
+                        image: images.add(thumbnail).into(),
+                        style: Style {
+                            size: Size::new(
+                                Val::Px(THUMBNAIL_HEIGHT * aspect),
+                                Val::Px(THUMBNAIL_HEIGHT),
+                            ),
+                            margin: UiRect::right(Val::Px(8.0)),
+                            ..default()
+                        },
+                        ..default()
+                    });
+                }
+                let status = if save.completed.contains(&level.iid) {
+                    "Complete"
+                } else {
+                    "Not completed"
+                };
+                button.spawn(TextBundle::from_sections([
+                    TextSection::new(
+                        format!("{}\n", level.identifier),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 24.0,
+                            color: Color::WHITE,
+                        },
+                    ),
+                    TextSection::new(
+                        status,
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 16.0,
+                            color: Color::GRAY,
+                        },
+                    ),
+                ]));
+            });
+        }
+    });
+}
+
+pub fn input(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut button_query: Query<
+        (&Interaction, &LevelButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut level_selection: ResMut<LevelSelection>,
+    mut ingame: ResMut<InGame>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::Menu));
+        return;
+    }
+    for (interaction, button, mut color) in &mut button_query {
+        match interaction {
+            Interaction::Clicked => {
+                *level_selection = LevelSelection::Iid(button.iid.clone());
+                ingame.0 = false;
+                commands.insert_resource(NextState(GameState::Game));
+            }
+            Interaction::Hovered => *color = BUTTON_HOVERED_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
@@ -1,18 +1,50 @@
 use bevy::prelude::*;
+use bevy_ecs_ldtk::LevelSelection;
 use iyes_loopless::state::NextState;
 
-use crate::components::{GameState, Menu};
+use crate::components::{GameState, MapPath, Menu};
+use crate::states::game::replay::{BeforeReplay, Replay, ReplayPlayback, LAST_REPLAY_PATH};
 
-pub fn setup(mut commands: Commands) {
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn((Menu, Camera2dBundle::default()));
+    commands.spawn((
+        Menu,
+        TextBundle::from_section(
+            "Esc: play\nL: level select\nR: watch last replay",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                font_size: 24.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Auto),
+            ..default()
+        }),
+    ));
 }
 
 pub fn input(
     mut commands: Commands,
     keys: Res<Input<KeyCode>>,
     mouse_buttons: Res<Input<MouseButton>>,
+    map_path: Res<MapPath>,
+    level_selection: Res<LevelSelection>,
 ) {
     if keys.just_pressed(KeyCode::Escape) {
         commands.insert_resource(NextState(GameState::Game));
+    } else if keys.just_pressed(KeyCode::L) {
+        commands.insert_resource(NextState(GameState::LevelSelect));
+    } else if keys.just_pressed(KeyCode::R) {
+        match Replay::load(LAST_REPLAY_PATH) {
+            Ok(replay) => {
+                commands.insert_resource(BeforeReplay::new(&map_path, &level_selection, &replay));
+                commands.insert_resource(MapPath(replay.map.clone()));
+                commands.insert_resource(LevelSelection::from(&replay.level));
+                commands.insert_resource(ReplayPlayback { replay, frame: 0 });
+                commands.insert_resource(NextState(GameState::Game));
+            }
+            Err(error) => warn!("Couldn't load {LAST_REPLAY_PATH}: {error}"),
+        }
     }
 }
@@ -1,3 +1,4 @@
 pub mod game;
+pub mod level_select;
 pub mod menu;
 pub mod splashscreen;
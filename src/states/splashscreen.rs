@@ -8,13 +8,14 @@ use bevy_tweening::{
 use iyes_loopless::prelude::*;
 
 use crate::components::{GameState, Splashscreen};
+use crate::tuning::Tuning;
 
 #[derive(Resource)]
 pub struct SplashTimer {
     timer: Timer,
 }
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, tuning: Res<Tuning>) {
     let tween = Tween::new(
         EaseFunction::SineIn,
         Duration::from_secs(3),
@@ -58,7 +59,10 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
     commands.insert_resource(SplashTimer {
         // create the repeating timer
-        timer: Timer::new(Duration::from_secs(5), TimerMode::Once),
+        timer: Timer::new(
+            Duration::from_secs_f32(tuning.splash_duration),
+            TimerMode::Once,
+        ),
     });
 }
 
@@ -16,6 +16,6 @@ pub fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<
 
 pub fn print_current_state(state: Res<CurrentState<GameState>>) {
     if state.is_changed() {
-        println!("{:?}", state.0);
+        info!(state = ?state.0, "Entered state");
     }
 }
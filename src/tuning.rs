@@ -0,0 +1,151 @@
+use bevy::{
+    asset::{AssetLoader, FileAssetIo, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+/// Relative to the assets folder. Bevy picks loaders by what follows a dot in the file name,
+/// `TuningLoader` claims "tuning.ron" so other .ron files are left alone.
+pub const TUNING_PATH: &str = "gameplay.tuning.ron";
+
+/// Gameplay values designers balance the game with, read from `TUNING_PATH`.
+/// Systems read the `Tuning` resource, which `apply_tuning` keeps in sync with the file.
+/// Values left out of the file keep their defaults.
+#[derive(Resource, Deserialize, TypeUuid, Clone, Debug, PartialEq)]
+#[uuid = "8d0f3c55-2b6e-4e4a-a1c7-5f6a3e9b7d21"]
+#[serde(default)]
+pub struct Tuning {
+    /// Pixels a second
+    pub player_speed: f32,
+    /// Pixels a second
+    pub enemy_speed: f32,
+    /// Of enemies spawned from the console, LDtk sets up the rest, see `colliders::ColliderSpec`.
+    pub collider_radius: f32,
+    /// Half the width and height of the sensor around dropped items, sized like a bottle in LDtk
+    pub item_sensor_half_size: Vec2,
+    /// How far in front of the player the held item is drawn
+    pub held_item_offset: f32,
+    /// Only read at startup, rapier can't change it afterwards
+    pub pixels_per_meter: f32,
+    /// Seconds before the splashscreen moves on to the menu
+    pub splash_duration: f32,
+    /// How quickly the camera catches up, higher is snappier
+    pub camera_damping: f32,
+    /// Width and height of the box around the camera's focus the player moves in freely
+    pub camera_deadzone: Vec2,
+    /// Pixels the camera looks ahead in the aim direction
+    pub camera_look_ahead: f32,
+    /// Pixels the camera moves at full trauma, see `camera::ScreenShake`
+    pub camera_shake_offset: f32,
+    /// Trauma lost a second
+    pub camera_shake_decay: f32,
+    /// Seconds a slide or fade between rooms takes, see `transitions::RoomTransition`
+    pub room_transition_duration: f32,
+    /// Enemies closer than this push away from each other, see `crowd::avoid_crowding`
+    pub enemy_separation_radius: f32,
+    /// How hard enemies push apart, as a fraction of `enemy_speed`
+    pub enemy_separation_strength: f32,
+    /// Seconds ahead enemies look for collisions with each other
+    pub enemy_avoidance_horizon: f32,
+    /// Enemies path around the cells other enemies stand on and are about to step into
+    pub reserve_enemy_cells: bool,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            player_speed: 200.0,
+            enemy_speed: 100.0,
+            collider_radius: 8.0,
+            item_sensor_half_size: Vec2::new(4.0, 4.0),
+            held_item_offset: 8.0,
+            pixels_per_meter: 16.0,
+            splash_duration: 5.0,
+            camera_damping: 8.0,
+            camera_deadzone: Vec2::new(32.0, 24.0),
+            camera_look_ahead: 24.0,
+            camera_shake_offset: 6.0,
+            camera_shake_decay: 1.5,
+            room_transition_duration: 0.5,
+            enemy_separation_radius: 24.0,
+            enemy_separation_strength: 1.0,
+            enemy_avoidance_horizon: 0.5,
+            reserve_enemy_cells: true,
+        }
+    }
+}
+
+impl Tuning {
+    /// Reads the file straight away, for the values needed before the asset server has loaded it.
+    /// Falls back to the defaults if it is missing or broken.
+    pub fn read_at_startup() -> Self {
+        let path = FileAssetIo::get_base_path()
+            .join("assets")
+            .join(TUNING_PATH);
+        match std::fs::read(&path) {
+            Ok(bytes) => ron::de::from_bytes(&bytes).unwrap_or_else(|error| {
+                warn!(path = %path.display(), "Couldn't parse tuning, using defaults: {error}");
+                Tuning::default()
+            }),
+            Err(error) => {
+                warn!(path = %path.display(), "Couldn't read tuning, using defaults: {error}");
+                Tuning::default()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TuningLoader;
+
+impl AssetLoader for TuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tuning: Tuning = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+/// Keeps the asset loaded, so changes to the file are picked up
+#[derive(Resource)]
+pub struct TuningHandle(pub Handle<Tuning>);
+
+pub fn load_tuning(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TuningHandle(asset_server.load(TUNING_PATH)));
+}
+
+/// Copies the asset into the `Tuning` resource whenever the file is loaded or edited
+pub fn apply_tuning(
+    mut tuning_events: EventReader<AssetEvent<Tuning>>,
+    tuning_assets: Res<Assets<Tuning>>,
+    tuning_handle: Res<TuningHandle>,
+    mut tuning: ResMut<Tuning>,
+) {
+    for event in tuning_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+                if *handle == tuning_handle.0 =>
+            {
+                if let Some(loaded) = tuning_assets.get(handle) {
+                    if *tuning != *loaded {
+                        info!("Reloaded {TUNING_PATH}");
+                        *tuning = loaded.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
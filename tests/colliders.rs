@@ -0,0 +1,42 @@
+//! Colliders set up through the optional fields on LDtk entities, see `colliders::ColliderSpec`.
+
+mod common;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{Collider, CollisionGroups, Sensor};
+use common::Scenario;
+use game_jam::states::game::{
+    colliders::PLAYER_GROUP,
+    components::{Enemy, ItemTag},
+};
+
+#[test]
+fn entity_fields_shape_the_colliders() {
+    let mut scenario = Scenario::load("fixtures/colliders.ldtk");
+    scenario.run_frames(1);
+    let player = scenario.player();
+    let world = &mut scenario.app.world;
+
+    let (collider, groups) = world
+        .query_filtered::<(&Collider, &CollisionGroups), With<Enemy>>()
+        .single(world);
+    let cuboid = collider.as_cuboid().expect("the enemy is set to a box");
+    assert_eq!(cuboid.half_extents(), Vec2::new(6.0, 10.0));
+    assert_eq!(groups.filters, PLAYER_GROUP);
+
+    let (collider, sensor) = world
+        .query_filtered::<(&Collider, Option<&Sensor>), With<ItemTag>>()
+        .single(world);
+    let ball = collider.as_ball().expect("the bottle is set to a ball");
+    assert_eq!(ball.radius(), 3.0);
+    assert!(sensor.is_some(), "bottles stay sensors by default");
+
+    let collider = world
+        .get::<Collider>(player)
+        .expect("the player has a collider");
+    let capsule = collider
+        .as_capsule()
+        .expect("the player is set to a capsule");
+    assert_eq!(capsule.radius(), 5.0);
+    assert_eq!(capsule.half_height(), 2.0);
+}
@@ -0,0 +1,232 @@
+//! Runs the game headlessly on a fixture map and drives it with scripted input, frame by frame.
+// Each test binary only uses part of the harness
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    input::{keyboard::KeyboardInput, ButtonState},
+    prelude::*,
+};
+use bevy_ecs_ldtk::{LdtkLevel, LevelSelection};
+use bevy_rapier2d::prelude::RapierConfiguration;
+use game_jam::{
+    components::{MapPath, Player},
+    headless::headless_app,
+    states::game::{
+        components::{Enemy, ItemTag, MainEnemyBundle, PathInit, Target, WorldMouseCoords},
+        console::Console,
+        replay::{add_playback, Replay, ReplayPlayback, ReplayRecorder, FIXED_TIMESTEP},
+    },
+    tuning::Tuning,
+};
+
+/// How long to wait for the fixture map to load before giving up
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The assets folder stays the root so the sprites the fixtures use still load
+fn map_path(map: &str) -> MapPath {
+    MapPath(format!("../tests/{map}"))
+}
+
+pub struct Scenario {
+    pub app: App,
+}
+
+impl Scenario {
+    /// Starts the game on `map`, a path relative to the tests folder,
+    /// and runs it until the player has spawned and the nav grid is built
+    pub fn load(map: &str) -> Scenario {
+        Scenario::start(|app| {
+            app.insert_resource(map_path(map));
+            // Physics steps by a fixed amount every frame, however fast the test runs
+            app.world
+                .resource_mut::<RapierConfiguration>()
+                .timestep_mode = FIXED_TIMESTEP;
+        })
+    }
+
+    /// Like `load`, recording the input from the frame the player spawns in, see `recording`
+    pub fn record(map: &str) -> Scenario {
+        Scenario::start(|app| {
+            app.insert_resource(map_path(map))
+                .insert_resource(ReplayRecorder::new(None));
+        })
+    }
+
+    /// Starts the map `replay` was recorded on, playing it back from the next frame
+    pub fn play(replay: Replay) -> Scenario {
+        Scenario::start(|app| {
+            add_playback(app, replay);
+        })
+    }
+
+    fn start(setup: impl FnOnce(&mut App)) -> Scenario {
+        let mut app = headless_app();
+        setup(&mut app);
+        let mut scenario = Scenario { app };
+
+        let started = Instant::now();
+        while !scenario.loaded() {
+            assert!(
+                started.elapsed() < LOAD_TIMEOUT,
+                "{} did not load within {LOAD_TIMEOUT:?}",
+                scenario.app.world.resource::<MapPath>().0
+            );
+            scenario.app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        scenario
+    }
+
+    fn loaded(&mut self) -> bool {
+        self.app.world.resource::<PathInit>().0
+            && self
+                .app
+                .world
+                .query_filtered::<(), (With<Player>, Without<ItemTag>)>()
+                .iter(&self.app.world)
+                .next()
+                .is_some()
+    }
+
+    pub fn press(&mut self, key_code: KeyCode) -> &mut Self {
+        self.send_key(key_code, ButtonState::Pressed)
+    }
+
+    pub fn release(&mut self, key_code: KeyCode) -> &mut Self {
+        self.send_key(key_code, ButtonState::Released)
+    }
+
+    /// Presses `key_code` for a single frame
+    pub fn tap(&mut self, key_code: KeyCode) -> &mut Self {
+        self.press(key_code).run_frames(1).release(key_code)
+    }
+
+    fn send_key(&mut self, key_code: KeyCode, state: ButtonState) -> &mut Self {
+        self.app
+            .world
+            .resource_mut::<Events<KeyboardInput>>()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key_code),
+                state,
+            });
+        self
+    }
+
+    /// Points the mouse at `target` in world coordinates
+    pub fn aim(&mut self, target: Vec2) -> &mut Self {
+        self.app.world.resource_mut::<WorldMouseCoords>().0 = target;
+        self
+    }
+
+    /// Sends every enemy to where the player stands, as if they had heard them there
+    pub fn alert_enemies(&mut self) -> &mut Self {
+        let player = self.player();
+        let player_position = self.position(player);
+        let world = &mut self.app.world;
+        for mut target in world
+            .query_filtered::<&mut Target, With<Enemy>>()
+            .iter_mut(world)
+        {
+            target.0 = Some(player_position);
+        }
+        self
+    }
+
+    /// Types `line` into the console and submits it, it runs on the next frame
+    pub fn console(&mut self, line: &str) -> &mut Self {
+        let mut console = self.app.world.resource_mut::<Console>();
+        console.input = line.to_string();
+        console.submit();
+        self
+    }
+
+    /// Spawns another main enemy at `translation`, the way the console's spawn command does
+    pub fn spawn_enemy(&mut self, translation: Vec3) -> Entity {
+        let asset_server = self.app.world.resource::<AssetServer>().clone();
+        let tuning = self.app.world.resource::<Tuning>().clone();
+        self.app
+            .world
+            .spawn(MainEnemyBundle::new(translation, &tuning, &asset_server))
+            .id()
+    }
+
+    pub fn run_frames(&mut self, frames: u32) -> &mut Self {
+        for _ in 0..frames {
+            self.app.update();
+        }
+        self
+    }
+
+    /// The input recorded so far by a scenario started with `record`
+    pub fn recording(&self) -> Replay {
+        self.app
+            .world
+            .resource::<ReplayRecorder>()
+            .replay
+            .clone()
+            .expect("recording starts on entering the game")
+    }
+
+    /// Runs until a scenario started with `play` has used up its replay
+    pub fn finish_replay(&mut self) -> &mut Self {
+        while self.app.world.contains_resource::<ReplayPlayback>() {
+            self.app.update();
+        }
+        self
+    }
+
+    /// Runs until `condition` holds, for at most `frames` frames. Returns whether it held.
+    pub fn run_until(
+        &mut self,
+        frames: u32,
+        mut condition: impl FnMut(&mut World) -> bool,
+    ) -> bool {
+        for _ in 0..frames {
+            self.app.update();
+            if condition(&mut self.app.world) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn player(&mut self) -> Entity {
+        self.app
+            .world
+            .query_filtered::<Entity, (With<Player>, Without<ItemTag>)>()
+            .single(&self.app.world)
+    }
+
+    pub fn position(&self, entity: Entity) -> Vec2 {
+        self.app
+            .world
+            .get::<Transform>(entity)
+            .expect("entity should have a transform")
+            .translation
+            .truncate()
+    }
+
+    /// The nav grid cell of a world position, counted from the bottom left of the current level
+    pub fn grid_cell(&mut self, position: Vec2) -> [i32; 2] {
+        let world = &mut self.app.world;
+        let mut level_query = world.query::<(&Transform, &Handle<LdtkLevel>)>();
+        let level_selection = world.resource::<LevelSelection>();
+        let ldtk_levels = world.resource::<Assets<LdtkLevel>>();
+        let level_position = level_query
+            .iter(world)
+            .find(|(_, level_handle)| {
+                ldtk_levels.get(level_handle).map_or(false, |ldtk_level| {
+                    level_selection.is_match(&0, &ldtk_level.level)
+                })
+            })
+            .expect("the current level should be loaded")
+            .0
+            .translation
+            .truncate();
+        let cell = (position - level_position) / 16.0;
+        [cell.x as i32, cell.y as i32]
+    }
+}
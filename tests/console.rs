@@ -0,0 +1,60 @@
+//! Console commands, typed in while playing `fixtures/scenario.ldtk`.
+
+mod common;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::{Collider, Sensor};
+use common::Scenario;
+use game_jam::{
+    components::Game,
+    states::game::{
+        components::{Enemy, ItemTag, MainEnemy},
+        console::Console,
+    },
+};
+
+#[test]
+fn spawn_puts_bottles_and_enemies_at_the_player() {
+    let mut scenario = Scenario::load("fixtures/scenario.ldtk");
+    let count = |world: &mut World| {
+        let bottles = world
+            .query_filtered::<(), (With<ItemTag>, With<Sensor>, With<Game>)>()
+            .iter(world)
+            .count();
+        let enemies = world
+            .query_filtered::<(), (With<Enemy>, With<Collider>)>()
+            .iter(world)
+            .count();
+        (bottles, enemies)
+    };
+    scenario.run_frames(1);
+    let (bottles, enemies) = count(&mut scenario.app.world);
+
+    scenario
+        .console("spawn bottle")
+        .console("spawn enemy")
+        .run_frames(3);
+    assert_eq!(count(&mut scenario.app.world), (bottles + 1, enemies + 1));
+    let output = &scenario.app.world.resource::<Console>().output;
+    assert!(output.contains(&"Spawned bottle".to_string()));
+    assert!(output.contains(&"Spawned enemy".to_string()));
+}
+
+#[test]
+fn enemies_ignore_the_player_in_god_mode() {
+    let mut scenario = Scenario::load("fixtures/scenario.ldtk");
+    let enemy = scenario
+        .app
+        .world
+        .query_filtered::<Entity, With<MainEnemy>>()
+        .single(&scenario.app.world);
+    let start = scenario.position(enemy);
+
+    // Right below the enemy, well within sight
+    let beside = start - Vec2::Y * 24.0;
+    scenario
+        .console("god")
+        .console(&format!("tp {} {}", beside.x, beside.y))
+        .run_frames(30);
+    assert!(scenario.position(enemy).distance(start) < 0.5);
+}
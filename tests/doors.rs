@@ -0,0 +1,35 @@
+//! Doors on `fixtures/door.ldtk`: the player starts left of a closed door
+//! that fills the gap in the dividing wall.
+
+mod common;
+
+use bevy::prelude::*;
+use common::Scenario;
+use game_jam::states::game::doors::Door;
+
+#[test]
+fn closed_doors_block_the_player_and_stay_put() {
+    let mut scenario = Scenario::load("fixtures/door.ldtk");
+    let player = scenario.player();
+    let world = &mut scenario.app.world;
+    let door = world.query_filtered::<Entity, With<Door>>().single(world);
+    let door_position = |world: &World| {
+        world
+            .get::<GlobalTransform>(door)
+            .expect("the door has a transform")
+            .translation()
+            .truncate()
+    };
+    let door_start = door_position(world);
+
+    scenario
+        .press(KeyCode::D)
+        .run_frames(120)
+        .release(KeyCode::D)
+        .run_frames(10);
+    let world = &scenario.app.world;
+    assert!(!world.get::<Door>(door).unwrap().open);
+    assert_eq!(door_position(world), door_start, "the door was pushed");
+    // The door is a cell wide, the player can't get past its left edge
+    assert!(scenario.position(player).x < door_start.x - 8.0);
+}
@@ -0,0 +1,125 @@
+//! Recording a scripted run on the fixture map and playing it back has to end up in the same place.
+
+mod common;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::LevelSelection;
+use bevy_rapier2d::prelude::RapierConfiguration;
+use common::Scenario;
+use game_jam::components::MapPath;
+use game_jam::states::game::{
+    components::{Items, MainEnemy},
+    replay::{Replay, FIXED_TIMESTEP},
+};
+
+const MAP: &str = "fixtures/scenario.ldtk";
+
+fn enemy_position(scenario: &mut Scenario) -> Vec2 {
+    let enemy = scenario
+        .app
+        .world
+        .query_filtered::<Entity, With<MainEnemy>>()
+        .single(&scenario.app.world);
+    scenario.position(enemy)
+}
+
+#[test]
+fn playback_reproduces_the_recording() {
+    let mut recording = Scenario::record(MAP);
+    recording
+        .tap(KeyCode::E)
+        .aim(Vec2::new(160.0, -40.0))
+        .press(KeyCode::S)
+        .run_frames(20)
+        .press(KeyCode::D)
+        .run_frames(15)
+        .release(KeyCode::S)
+        .run_frames(15)
+        .release(KeyCode::D)
+        .run_frames(10);
+    let replay = recording.recording();
+    let player = recording.player();
+    let recorded_player = *recording.app.world.get::<Transform>(player).unwrap();
+    let recorded_enemy = enemy_position(&mut recording);
+
+    let mut playback = Scenario::play(replay);
+    playback.finish_replay();
+    let player = playback.player();
+    let played_player = *playback.app.world.get::<Transform>(player).unwrap();
+
+    assert_eq!(
+        playback.app.world.get::<Items>(player),
+        Some(&Items::GlassBottle)
+    );
+    assert!(
+        played_player
+            .translation
+            .distance(recorded_player.translation)
+            < 0.01,
+        "player ended at {} instead of {}",
+        played_player.translation,
+        recorded_player.translation
+    );
+    assert!(
+        played_player
+            .rotation
+            .angle_between(recorded_player.rotation)
+            < 0.001
+    );
+    assert!(enemy_position(&mut playback).distance(recorded_enemy) < 0.01);
+}
+
+#[test]
+fn the_next_session_after_a_replay_is_back_to_normal() {
+    let mut recording = Scenario::record(MAP);
+    recording.press(KeyCode::D).run_frames(5);
+    let replay = recording.recording();
+
+    let mut playback = Scenario::play(replay);
+    let timestep_mode = |scenario: &Scenario| {
+        scenario
+            .app
+            .world
+            .resource::<RapierConfiguration>()
+            .timestep_mode
+    };
+    assert_eq!(timestep_mode(&playback), FIXED_TIMESTEP);
+    playback
+        .finish_replay()
+        .console("state menu")
+        .run_frames(1)
+        .console("state game")
+        .run_frames(1);
+
+    let world = &playback.app.world;
+    assert_eq!(world.resource::<MapPath>().0, MapPath::default().0);
+    assert_eq!(
+        *world.resource::<LevelSelection>(),
+        LevelSelection::default()
+    );
+    assert_eq!(
+        timestep_mode(&playback),
+        RapierConfiguration::default().timestep_mode
+    );
+}
+
+#[test]
+fn replays_survive_a_round_trip_through_a_file() {
+    let mut recording = Scenario::record(MAP);
+    recording
+        .press(KeyCode::W)
+        .run_frames(5)
+        .release(KeyCode::W)
+        .run_frames(1);
+    let replay = recording.recording();
+    let frames_holding_w = replay
+        .frames
+        .iter()
+        .filter(|frame| frame.keys.contains(&KeyCode::W))
+        .count();
+    assert_eq!(frames_holding_w, 5);
+
+    let path = std::env::temp_dir().join("game-jam-replay-round-trip.ron");
+    replay.save(&path).unwrap();
+    assert_eq!(Replay::load(&path).unwrap(), replay);
+}
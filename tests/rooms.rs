@@ -0,0 +1,90 @@
+//! Neighbouring rooms on `fixtures/rooms.ldtk`. The player starts in West, which has an exit
+//! in its top left and leads to East through a gap at the bottom of the wall between them.
+//! East has the main enemy and a collectible on the far side of a wall with a gap at the top.
+
+mod common;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::LevelSelection;
+use common::Scenario;
+use game_jam::save::SaveData;
+use game_jam::states::game::{
+    components::{Enemy, PathInit, PathfindingMap, TargetPath},
+    objectives::{LevelProgress, Objective},
+    transitions::RoomTransition,
+};
+
+const MAP: &str = "fixtures/rooms.ldtk";
+const WEST: &str = "0c0a7e30-6b1e-11ee-9a4c-1b2d9f0e7c01";
+const EAST: &str = "0c0a7e30-6b1e-11ee-9a4c-1b2d9f0e7c02";
+
+#[test]
+fn crossing_into_a_loaded_room_builds_its_nav_grid() {
+    let mut scenario = Scenario::load(MAP);
+    let crossed = scenario.press(KeyCode::D).run_until(120, |world| {
+        matches!(world.resource::<LevelSelection>(), LevelSelection::Iid(iid) if iid == EAST)
+    });
+    scenario.release(KeyCode::D);
+    assert!(crossed, "the player never made it to East");
+    let settled = scenario.run_until(120, |world| {
+        !world.contains_resource::<RoomTransition>() && world.resource::<PathInit>().0
+    });
+    assert!(settled);
+
+    let player = scenario.player();
+    let player_cell = scenario.grid_cell(scenario.position(player));
+    // West has nothing in the middle, East has a wall down column 6 with a gap at the top
+    let around_the_wall = scenario.alert_enemies().run_until(20, |world| {
+        let path = world
+            .query_filtered::<&TargetPath, With<Enemy>>()
+            .single(world)
+            .0
+            .clone();
+        let path_map = &world.resource::<PathfindingMap>().path_map;
+        path_map.is_obstacle([6, 4])
+            && path.last() == Some(&player_cell)
+            && path.iter().all(|cell| !path_map.is_obstacle(*cell))
+            && path.iter().any(|cell| cell[0] == 6 && cell[1] >= 9)
+    });
+    assert!(
+        around_the_wall,
+        "the enemy should path to {player_cell:?} through the gap in East's wall"
+    );
+}
+
+#[test]
+fn reaching_the_exit_moves_on_to_the_next_level_in_ldtk() {
+    let mut scenario = Scenario::load(MAP);
+    let world = &mut scenario.app.world;
+    let exit = world
+        .query::<(&Objective, &GlobalTransform)>()
+        .iter(world)
+        .find(|(objective, _)| **objective == Objective::ReachExit)
+        .expect("West has an exit")
+        .1
+        .translation();
+    // The collectible in East doesn't hold up finishing West
+    scenario
+        .console(&format!("tp {} {}", exit.x, exit.y))
+        .run_frames(3);
+
+    let world = &mut scenario.app.world;
+    let progress = world.resource::<LevelProgress>();
+    assert!(progress.finished.is_some(), "West wasn't finished");
+    assert_eq!(progress.next_level.as_deref(), Some("East"));
+    // Kept in the save, for the level select
+    assert!(world.resource::<SaveData>().completed.contains(WEST));
+    let results: String = world
+        .query::<&Text>()
+        .iter(world)
+        .flat_map(|text| text.sections.iter().map(|section| section.value.clone()))
+        .collect();
+    assert!(results.contains("Reach the exit"));
+    assert!(!results.contains("ledger"), "East's goals were listed");
+
+    scenario.tap(KeyCode::Return).run_frames(1);
+    assert!(matches!(
+        scenario.app.world.resource::<LevelSelection>(),
+        LevelSelection::Identifier(identifier) if identifier == "East"
+    ));
+}
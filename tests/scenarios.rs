@@ -0,0 +1,420 @@
+//! Gameplay scenarios on `fixtures/scenario.ldtk`: a 12x12 room with the player and a bottle
+//! in the top left and the main enemy in the top right, split by a wall with a gap at the bottom.
+
+mod common;
+
+use bevy::{hierarchy::despawn_with_children_recursive, prelude::*};
+use bevy_ecs_ldtk::{GridCoords, LdtkAsset, LdtkLevel};
+use bevy_rapier2d::prelude::{Collider, RigidBody, Sensor};
+use common::Scenario;
+use game_jam::{
+    components::Player,
+    save::SaveData,
+    states::game::{
+        components::{
+            Enemy, ItemTag, Items, MainEnemy, NoiseEvent, PathInit, PathfindingMap, TargetPath,
+        },
+        lighting::LightMap,
+        post_processing::PostProcessingStack,
+        terrain::{TerrainMap, TerrainType},
+    },
+};
+
+const MAP: &str = "fixtures/scenario.ldtk";
+
+#[test]
+fn player_moves_with_wasd() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let start = scenario.position(player);
+
+    scenario
+        .press(KeyCode::S)
+        .run_frames(10)
+        .release(KeyCode::S);
+    let moved = scenario.position(player);
+    assert!(moved.y < start.y, "{moved} should be below {start}");
+    assert!((moved.x - start.x).abs() < 0.5);
+
+    // Nothing held, the player stops
+    scenario.run_frames(2);
+    let stopped = scenario.position(player);
+    scenario.run_frames(10);
+    assert!(scenario.position(player).distance(stopped) < 0.5);
+}
+
+#[test]
+fn walls_stop_the_player() {
+    let mut scenario = Scenario::load(MAP);
+    let fixed_colliders = scenario
+        .app
+        .world
+        .query::<(&Collider, &RigidBody)>()
+        .iter(&scenario.app.world)
+        .filter(|(_, rigid_body)| **rigid_body == RigidBody::Fixed)
+        .count();
+    assert!(fixed_colliders > 0, "rebuild_wall_chunks made no colliders");
+
+    // The level starts at x 0 and its first column is wall,
+    // the player's ball collider has a radius of 8
+    let player = scenario.player();
+    scenario.press(KeyCode::A).run_frames(60);
+    let x = scenario.position(player).x;
+    assert!(x >= 24.0 - 1.0, "player at {x} went into the wall");
+    assert!(x <= 24.0 + 1.0, "player at {x} stopped short of the wall");
+}
+
+#[test]
+fn pressing_e_picks_up_the_bottle() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    assert_eq!(scenario.app.world.get::<Items>(player), Some(&Items::None));
+
+    scenario.tap(KeyCode::E).run_frames(2);
+
+    assert_eq!(
+        scenario.app.world.get::<Items>(player),
+        Some(&Items::GlassBottle)
+    );
+    let bottles_on_floor = scenario
+        .app
+        .world
+        .query_filtered::<&Items, (With<ItemTag>, Without<Player>)>()
+        .iter(&scenario.app.world)
+        .filter(|item| **item == Items::GlassBottle)
+        .count();
+    assert_eq!(bottles_on_floor, 0);
+}
+
+#[test]
+fn player_faces_the_mouse() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let position = scenario.position(player);
+
+    scenario.aim(position + Vec2::X * 32.0).run_frames(1);
+
+    // The sprite faces up by default, so looking right is a quarter turn clockwise
+    let rotation = scenario
+        .app
+        .world
+        .get::<Transform>(player)
+        .unwrap()
+        .rotation;
+    let facing = rotation * Vec3::Y;
+    assert!(
+        facing.truncate().distance(Vec2::X) < 0.01,
+        "facing {facing}"
+    );
+}
+
+#[test]
+fn enemy_path_reaches_the_player_around_the_wall() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let player_cell = scenario.grid_cell(scenario.position(player));
+
+    let reached = scenario.alert_enemies().run_until(10, |world| {
+        world
+            .query_filtered::<&TargetPath, With<MainEnemy>>()
+            .single(world)
+            .0
+            .last()
+            == Some(&player_cell)
+    });
+    assert!(reached, "enemy path never reached {player_cell:?}");
+
+    let path = &scenario
+        .app
+        .world
+        .query_filtered::<&TargetPath, With<MainEnemy>>()
+        .single(&scenario.app.world)
+        .0;
+    // Straight across is 7 cells, the wall in between makes it go down to the gap and back up
+    assert!(path.len() > 8, "path {path:?} went through the wall");
+}
+
+#[test]
+fn enemies_only_go_after_what_they_notice() {
+    let mut scenario = Scenario::load(MAP);
+    let enemy = scenario
+        .app
+        .world
+        .query_filtered::<Entity, With<MainEnemy>>()
+        .single(&scenario.app.world);
+    let start = scenario.position(enemy);
+
+    // The player is behind the wall and out of range
+    scenario.run_frames(30);
+    assert!(scenario.position(enemy).distance(start) < 0.5);
+
+    // Further down the enemy's side of the wall
+    let noise = start - Vec2::Y * 64.0;
+    let noise_cell = scenario.grid_cell(noise);
+    scenario
+        .app
+        .world
+        .resource_mut::<Events<NoiseEvent>>()
+        .send(NoiseEvent {
+            position: noise,
+            loudness: 96.0,
+            footstep: false,
+        });
+    let heard = scenario.run_until(5, |world| {
+        world
+            .query_filtered::<&TargetPath, With<MainEnemy>>()
+            .single(world)
+            .0
+            .last()
+            == Some(&noise_cell)
+    });
+    assert!(heard, "the enemy didn't go to look at {noise_cell:?}");
+}
+
+#[test]
+fn being_seen_closes_in_the_vignette() {
+    let mut scenario = Scenario::load(MAP);
+    scenario.run_frames(5);
+    let stack = scenario.app.world.resource::<PostProcessingStack>();
+    assert_eq!(stack.vignette.intensity, 0.0);
+
+    scenario.console("spawn enemy").run_frames(2);
+    let stack = scenario.app.world.resource::<PostProcessingStack>();
+    assert!(stack.vignette.intensity > 0.0);
+    assert!(
+        stack.flash.intensity > 0.0,
+        "no flash when the player was first seen"
+    );
+}
+
+#[test]
+fn colliders_take_the_size_of_their_ldtk_entity() {
+    let mut scenario = Scenario::load(MAP);
+    scenario.run_frames(1);
+    let world = &mut scenario.app.world;
+
+    let enemy = world
+        .query_filtered::<&Collider, (With<MainEnemy>, Without<Sensor>)>()
+        .single(world);
+    assert_eq!(enemy.as_ball().map(|ball| ball.radius()), Some(8.0));
+
+    // Bottles are 8x8 in LDtk, and only sense the player rather than blocking them
+    let bottle = world
+        .query_filtered::<&Collider, (With<ItemTag>, Without<Player>, With<Sensor>)>()
+        .single(world);
+    let half_extents = bottle.as_cuboid().map(|cuboid| cuboid.half_extents());
+    assert_eq!(half_extents, Some(Vec2::splat(4.0)));
+}
+
+/// Whether both enemies have a path from where they stand to `player_cell`,
+/// in steps to neighbouring cells and around the walls
+fn paths_are_valid(world: &mut World, player_cell: [i32; 2]) -> bool {
+    let level_position = world
+        .query_filtered::<&Transform, With<Handle<LdtkLevel>>>()
+        .single(world)
+        .translation
+        .truncate();
+    let enemies: Vec<(Vec2, Vec<[i32; 2]>)> = world
+        .query_filtered::<(&Transform, &TargetPath), With<Enemy>>()
+        .iter(world)
+        .map(|(transform, path)| (transform.translation.truncate(), path.0.clone()))
+        .collect();
+    let path_map = &world.resource::<PathfindingMap>().path_map;
+    let neighbours =
+        |a: [i32; 2], b: [i32; 2]| (a[0] - b[0]).abs() <= 1 && (a[1] - b[1]).abs() <= 1;
+    enemies.len() == 2
+        && enemies.iter().all(|(position, path)| {
+            // The enemy may have stepped into the next cell since it searched
+            let cell = ((*position - level_position) / 16.0).floor();
+            path.first().map_or(false, |first| {
+                neighbours(*first, [cell.x as i32, cell.y as i32])
+            }) && path.last() == Some(&player_cell)
+                && path.windows(2).all(|step| neighbours(step[0], step[1]))
+                && path.iter().all(|cell| !path_map.is_obstacle(*cell))
+        })
+}
+
+#[test]
+fn every_enemy_finds_its_own_path() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let player_cell = scenario.grid_cell(scenario.position(player));
+    let main_enemy = scenario
+        .app
+        .world
+        .query_filtered::<Entity, With<MainEnemy>>()
+        .single(&scenario.app.world);
+    // Further down the right side of the wall
+    let translation = scenario.position(main_enemy).extend(0.0) - Vec3::Y * 64.0;
+    scenario.spawn_enemy(translation);
+
+    let reached = scenario
+        .alert_enemies()
+        .run_until(10, |world| paths_are_valid(world, player_cell));
+    assert!(reached, "both enemies should path to {player_cell:?}");
+    // The enemies search one after the other every frame,
+    // anything left over from the other one's search would break a path
+    for _ in 0..30 {
+        scenario.run_frames(1);
+        assert!(paths_are_valid(&mut scenario.app.world, player_cell));
+    }
+    let colliders = scenario
+        .app
+        .world
+        .query_filtered::<(), (With<Enemy>, With<Collider>)>()
+        .iter(&scenario.app.world)
+        .count();
+    assert_eq!(colliders, 2);
+}
+
+#[test]
+fn enemies_path_around_each_other() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let player_cell = scenario.grid_cell(scenario.position(player));
+    let main_enemy = scenario
+        .app
+        .world
+        .query_filtered::<Entity, With<MainEnemy>>()
+        .single(&scenario.app.world);
+    // Right behind the main enemy on its way down to the gap
+    let translation = scenario.position(main_enemy).extend(0.0) - Vec3::Y * 16.0;
+    scenario.spawn_enemy(translation);
+
+    let spread_out = scenario.alert_enemies().run_until(10, |world| {
+        let enemies: Vec<(Vec2, Vec<[i32; 2]>)> = world
+            .query_filtered::<(&Transform, &TargetPath), With<Enemy>>()
+            .iter(world)
+            .map(|(transform, path)| (transform.translation.truncate(), path.0.clone()))
+            .collect();
+        let level_position = world
+            .query_filtered::<&Transform, With<Handle<LdtkLevel>>>()
+            .single(world)
+            .translation
+            .truncate();
+        let cell = |position: Vec2| {
+            let cell = (position - level_position) / 16.0;
+            [cell.x as i32, cell.y as i32]
+        };
+        enemies
+            .iter()
+            .all(|(_, path)| path.last() == Some(&player_cell))
+            && enemies.iter().all(|(_, path)| {
+                enemies.iter().all(|(other_position, other_path)| {
+                    std::ptr::eq(path, other_path)
+                        || !path
+                            .iter()
+                            .skip(1)
+                            .any(|step| *step == cell(*other_position))
+                })
+            })
+    });
+    assert!(spread_out, "one enemy kept pathing through the other");
+}
+
+#[test]
+fn knocking_down_the_wall_rebuilds_its_colliders_and_nav() {
+    let mut scenario = Scenario::load(MAP);
+    let player = scenario.player();
+    let player_cell = scenario.grid_cell(scenario.position(player));
+    let world = &mut scenario.app.world;
+    let level_position = world
+        .query_filtered::<&Transform, With<Handle<LdtkLevel>>>()
+        .single(world)
+        .translation
+        .truncate();
+    let wall_cell = GridCoords { x: 6, y: 6 };
+    let where_the_wall_was = level_position + Vec2::new(6.5, 6.5) * 16.0;
+    assert_eq!(
+        world.resource::<TerrainMap>().at(where_the_wall_was),
+        Some(TerrainType::Wall)
+    );
+
+    // The dividing wall is column 6, from just above the gap up to the outer wall
+    let dividing_wall: Vec<Entity> = world
+        .query::<(Entity, &GridCoords, &TerrainType)>()
+        .iter(world)
+        .filter(|(_, cell, terrain)| {
+            cell.x == 6 && (3..=10).contains(&cell.y) && **terrain == TerrainType::Wall
+        })
+        .map(|(tile, _, _)| tile)
+        .collect();
+    assert_eq!(dividing_wall.len(), 8);
+    for tile in dividing_wall {
+        despawn_with_children_recursive(world, tile);
+    }
+
+    let straight_across = scenario.alert_enemies().run_until(20, |world| {
+        let path = &world
+            .query_filtered::<&TargetPath, With<MainEnemy>>()
+            .single(world)
+            .0;
+        path.last() == Some(&player_cell) && path.len() <= 8
+    });
+    assert!(straight_across, "the nav grid still had the wall in it");
+
+    let world = &mut scenario.app.world;
+    let blocked = world
+        .query::<(&Collider, &RigidBody, &GlobalTransform)>()
+        .iter(world)
+        .any(|(collider, rigid_body, transform)| {
+            *rigid_body == RigidBody::Fixed
+                && collider.contains_point(
+                    transform.translation().truncate(),
+                    0.0,
+                    where_the_wall_was,
+                )
+        });
+    assert!(!blocked, "a collider was left where the wall was");
+    assert_eq!(world.resource::<TerrainMap>().at(where_the_wall_was), None);
+    assert!(!world.resource::<LightMap>().is_wall(wall_cell));
+}
+
+#[test]
+fn reloading_the_map_rebuilds_the_nav_grid() {
+    let mut scenario = Scenario::load(MAP);
+    let world = &mut scenario.app.world;
+    let is_obstacle = |world: &World, cell: [i32; 2]| {
+        world
+            .resource::<PathfindingMap>()
+            .path_map
+            .is_obstacle(cell)
+    };
+    assert!(is_obstacle(world, [6, 6]));
+    assert!(!is_obstacle(world, [6, 2]));
+
+    // Edit the map as if it was saved in LDtk: move the gap in the dividing wall to the top
+    let level_handle = world.query::<&Handle<LdtkLevel>>().single(world).clone();
+    let mut levels = world.resource_mut::<Assets<LdtkLevel>>();
+    let level = levels.get_mut(&level_handle).expect("the level is loaded");
+    for layer in level.level.layer_instances.iter_mut().flatten() {
+        if layer.int_grid_csv.is_empty() {
+            continue;
+        }
+        for (y, value) in [(1, 1), (2, 1), (6, 0)] {
+            let row = layer.c_hei - 1 - y;
+            layer.int_grid_csv[(row * layer.c_wid + 6) as usize] = value;
+        }
+    }
+    let project_handle = world.query::<&Handle<LdtkAsset>>().single(world).clone();
+    // Borrowing the project mutably is what tells bevy_ecs_ldtk it changed
+    world
+        .resource_mut::<Assets<LdtkAsset>>()
+        .get_mut(&project_handle);
+
+    let rebuilt = scenario.run_until(120, |world| {
+        world.resource::<PathInit>().0 && is_obstacle(world, [6, 2]) && !is_obstacle(world, [6, 6])
+    });
+    assert!(rebuilt, "the nav grid kept the map from before the reload");
+    assert!(is_obstacle(&scenario.app.world, [6, 1]));
+}
+
+#[test]
+fn the_starting_room_is_explored() {
+    let mut scenario = Scenario::load(MAP);
+    scenario.run_frames(1);
+    let save = scenario.app.world.resource::<SaveData>();
+    assert!(save
+        .explored
+        .contains("0c0a7e30-6b1e-11ee-9a4c-1b2d9f0e7a01"));
+}
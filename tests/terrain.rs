@@ -0,0 +1,89 @@
+//! The terrain table: what each type of IntGrid terrain blocks, and what is underfoot where,
+//! and how solid terrain is merged into colliders chunk by chunk.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::GridCoords;
+use bevy_rapier2d::prelude::Group;
+use game_jam::states::game::{
+    colliders::{ENEMY_GROUP, PLAYER_GROUP},
+    terrain::{AgentClass, TerrainMap, TerrainType},
+    wall_chunks::{chunk_of, merge_rects, GridRect, CHUNK_SIZE},
+};
+
+#[test]
+fn terrain_blocks_whoever_cant_walk_on_it() {
+    let filters = |terrain: TerrainType| terrain.collision_groups().map(|groups| groups.filters);
+    assert_eq!(filters(TerrainType::Wall), Some(Group::ALL));
+    assert_eq!(
+        filters(TerrainType::LowCover),
+        Some(PLAYER_GROUP | ENEMY_GROUP)
+    );
+    assert_eq!(filters(TerrainType::Water), Some(ENEMY_GROUP));
+    assert_eq!(filters(TerrainType::Bush), None);
+
+    assert!(TerrainType::Water.walkable_by(AgentClass::Player));
+    assert!(!TerrainType::Water.walkable_by(AgentClass::Enemy));
+}
+
+#[test]
+fn speed_depends_on_the_cell_underfoot() {
+    let terrain_map = TerrainMap {
+        origin: Vec2::new(-32.0, 0.0),
+        grid_size: 16.0,
+        cells: HashMap::from([(GridCoords { x: 1, y: 2 }, TerrainType::Water)]),
+    };
+    assert_eq!(
+        terrain_map.at(Vec2::new(-10.0, 40.0)),
+        Some(TerrainType::Water)
+    );
+    assert_eq!(
+        terrain_map.speed_at(Vec2::new(-10.0, 40.0)),
+        TerrainType::Water.rules().speed
+    );
+    // Bare floor
+    assert_eq!(terrain_map.speed_at(Vec2::new(10.0, 40.0)), 1.0);
+}
+
+#[test]
+fn walls_merge_into_rectangles_within_their_chunk() {
+    // An L: a 3 cell tall column with a 2 cell foot sticking out to the right
+    let solid = |cell: GridCoords| (cell.x == 1 && cell.y < 3) || (cell.y == 0 && cell.x == 2);
+    let mut rects = merge_rects(IVec2::ZERO, IVec2::splat(4), solid);
+    rects.sort_by_key(|rect| rect.bottom);
+    assert_eq!(
+        rects,
+        vec![
+            GridRect {
+                left: 1,
+                right: 2,
+                bottom: 0,
+                top: 0,
+            },
+            GridRect {
+                left: 1,
+                right: 1,
+                bottom: 1,
+                top: 2,
+            },
+        ]
+    );
+
+    // A row of wall across a chunk edge is split into one rectangle on either side
+    let edge = CHUNK_SIZE;
+    assert_eq!(chunk_of(GridCoords { x: edge - 1, y: 0 }), IVec2::new(0, 0));
+    assert_eq!(chunk_of(GridCoords { x: edge, y: 0 }), IVec2::new(1, 0));
+    let second_chunk = merge_rects(IVec2::new(edge, 0), IVec2::new(edge * 2, edge), |cell| {
+        cell.y == 0 && cell.x >= edge - 2 && cell.x <= edge + 1
+    });
+    assert_eq!(
+        second_chunk,
+        vec![GridRect {
+            left: edge,
+            right: edge + 1,
+            bottom: 0,
+            top: 0,
+        }]
+    );
+}
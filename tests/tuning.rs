@@ -0,0 +1,44 @@
+//! The shipped tuning file has to parse, starts out matching the values in code,
+//! and gets loaded into the `Tuning` resource by the asset server.
+
+use std::time::{Duration, Instant};
+
+use game_jam::{
+    headless::headless_app,
+    tuning::{Tuning, TUNING_PATH},
+};
+
+#[test]
+fn shipped_tuning_matches_defaults() {
+    let path = format!("{}/assets/{TUNING_PATH}", env!("CARGO_MANIFEST_DIR"));
+    let tuning: Tuning = ron::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(tuning, Tuning::default());
+}
+
+#[test]
+fn missing_values_keep_their_defaults() {
+    let tuning: Tuning = ron::from_str("(player_speed: 50.0)").unwrap();
+    assert_eq!(tuning.player_speed, 50.0);
+    assert_eq!(tuning.enemy_speed, Tuning::default().enemy_speed);
+}
+
+#[test]
+fn the_asset_server_applies_the_tuning_file() {
+    let mut app = headless_app();
+    // Anything but what the file says, so it shows when the file is applied
+    app.insert_resource(Tuning {
+        player_speed: 0.0,
+        ..Tuning::default()
+    });
+
+    let started = Instant::now();
+    while app.world.resource::<Tuning>().player_speed == 0.0 {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "{TUNING_PATH} was never loaded"
+        );
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(*app.world.resource::<Tuning>(), Tuning::default());
+}